@@ -1,191 +1,4432 @@
+//!
+//! Deserialization of env pairs into a tree ([`Envs`]) and from that tree
+//! into any type that implements [`Deserialize`].
+//!
+//! Env pairs are flat (`CONFIG__DATABASE__NAME=name`), so before driving any
+//! serde visitor, the pairs are being sorted & folded into a tree where each
+//! key segment becomes a branch and the last key segment becomes a leaf node
+//! that holds the value.
+//!
+use crate::{
+    error::DeserializeError,
+    serde::de::{self, Deserialize, DeserializeOwned, Unexpected, Visitor},
+};
+
 use std::{
+    borrow::Cow,
+    cell::RefCell,
     cmp::Ordering,
-    collections::{BinaryHeap, HashMap, HashSet},
+    collections::{HashMap, HashSet},
+    env,
+    ffi::OsString,
     io::{BufRead, Cursor},
-    iter::FromIterator,
+    rc::Rc,
 };
 
-#[derive(Debug, Clone)]
-pub(crate) struct EnvPair {
-    fields: Vec<String>,
-    value: String,
+/// Default character that marks a line as comment.
+pub const DEFAULT_COMMENT: char = '#';
+
+/// Default separator between key & value.
+pub const DEFAULT_KV_SEP: char = '=';
+
+/// Default separator between each fields in key.
+pub const DEFAULT_KEY_SEP: &str = "__";
+
+/// Default separator between each element in inline array value.
+pub const DEFAULT_ARRAY_SEP: char = ',';
+
+/// Default field that names the variant of internally tagged enum.
+pub const DEFAULT_TAG: &str = "TYPE";
+
+/// Name of the entry that holds the leaf value of a node that is
+/// also a branch, when [`Conflict::ValueEntry`] policy is used.
+pub const VALUE_KEY: &str = "_value";
+
+/// Policy for a key that is both a leaf & a branch.
+///
+/// `CONFIG__DATABASE=foo` and `CONFIG__DATABASE__NAME=bar` makes
+/// `DATABASE` both a value & a branch.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// Fails with [`DeserializeError::Conflict`].
+    Error,
+    /// Drop the leaf value, keep the branch.
+    PreferBranch,
+    /// Drop the branch, keep the leaf value.
+    PreferLeaf,
+    /// Keep both, the leaf value is moved into [`VALUE_KEY`] entry of the branch.
+    ValueEntry,
 }
 
-impl EnvPair {
+impl Default for Conflict {
+    #[inline]
+    fn default() -> Self {
+        Self::Error
+    }
+}
+
+/// Policy for pairs that have the same key.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplicate {
+    /// Keep the first pair in source order.
+    FirstWins,
+    /// Keep the last pair in source order (the same as dotenv & shell).
+    LastWins,
+    /// Fails with [`DeserializeError::Duplicate`] listing lines of every duplicate
+    /// (wrapped in [`DeserializeError::Multiple`] when more than one key is duplicated).
+    Error,
+}
+
+impl Default for Duplicate {
+    #[inline]
+    fn default() -> Self {
+        Self::LastWins
+    }
+}
+
+/// Order of sibling nodes in [`Envs`] tree.
+///
+/// Index fields (`SERVERS__2`, `SERVERS__10`) are always ordered numerically
+/// and placed before the other fields.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// Order fields lexicographically.
+    Sorted,
+    /// Keep fields in order of their first appearance in the source.
+    Source,
+}
+
+impl Default for Order {
     #[inline]
-    pub fn from_str<'a>(line: &'a str, comment: char, kv_sep: char, key_sep: &str) -> Option<Self> {
-        let line = line.trim();
+    fn default() -> Self {
+        Self::Sorted
+    }
+}
+
+/// How key segments & unit variant values are matched against
+/// struct fields & enum variants.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    /// Match ignoring ASCII case (`ENV=development` matches `Env::Development`),
+    /// fails with [`DeserializeError::Ambiguous`] when more than one name matches.
+    ///
+    /// Only known names are matched, map keys (including fields of
+    /// `#[serde(flatten)]` struct & internally tagged enum) are kept as is.
+    /// Map keys are deliberately not converted by the case policy, since
+    /// serde only knows them as map keys and folding them would break
+    /// flattened & tagged fields that are matched exactly (`tag = "TYPE"`).
+    Insensitive,
+    /// Match exactly.
+    Strict,
+}
+
+impl Default for Case {
+    #[inline]
+    fn default() -> Self {
+        Self::Insensitive
+    }
+}
 
-        if line.starts_with(comment) {
-            return None;
+impl Case {
+    #[inline]
+    fn matches(self, lhs: &str, rhs: &str) -> bool {
+        match self {
+            Self::Insensitive => lhs.eq_ignore_ascii_case(rhs),
+            Self::Strict => lhs == rhs,
         }
+    }
 
-        let pair = line
-            .splitn(2, kv_sep)
-            .map(move |line| line.to_string())
-            .collect::<Vec<_>>();
+    /// Name in `names` that `name` refers to, `None` when `name` is
+    /// already one of `names` or doesn't match any of them.
+    ///
+    fn resolve(
+        self,
+        name: &str,
+        names: &'static [&'static str],
+    ) -> Result<Option<&'static str>, DeserializeError> {
+        if self == Self::Strict || names.contains(&name) {
+            return Ok(None);
+        }
 
-        match &pair[..] {
-            [key, value] => {
-                let fields = key
-                    .split(key_sep)
-                    .map(move |line| line.to_string())
-                    .collect::<Vec<_>>();
+        let candidates = names
+            .iter()
+            .copied()
+            .filter(|candidate| self.matches(candidate, name))
+            .collect::<Vec<_>>();
 
-                Some(Self {
-                    fields: fields,
-                    value: value.clone(),
-                })
-            }
-            _ => None,
+        match candidates.len() {
+            0 => Ok(None),
+            1 => Ok(Some(candidates[0])),
+            _ => Err(DeserializeError::Ambiguous {
+                path: None,
+                line: None,
+                name: name.to_string(),
+                candidates,
+            }),
         }
     }
 }
 
-impl PartialEq for EnvPair {
+/// Policy for empty value (`KEY=`) of an `Option` field.
+///
+/// `Serializer::serialize_none` writes an empty value, thus [`Empty::None`]
+/// is the one that round-trips `None`. Quoted empty value (`KEY=""`)
+/// is always `Some("")`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Empty {
+    /// Empty value is `None`.
+    None,
+    /// Empty value is `Some` of an empty value (`Some("")`).
+    Some,
+    /// Fails with [`DeserializeError::InvalidValue`].
+    Error,
+}
+
+impl Default for Empty {
     #[inline]
-    fn eq(&self, other: &EnvPair) -> bool {
-        self.fields.eq(&other.fields)
+    fn default() -> Self {
+        Self::None
     }
 }
 
-impl Eq for EnvPair {}
+/// Which scalar types are being inferred from value text when the target
+/// type doesn't tell (`deserialize_any`, e.g. `serde_json::Value`,
+/// `#[serde(untagged)]` & `#[serde(flatten)]`).
+///
+/// Value that isn't inferred is a string.
+///
+/// ```
+/// use envit::de::{Infer, Options};
+///
+/// let options = Options::default().infer(Infer::all().array(false));
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Infer {
+    bool: bool,
+    integer: bool,
+    float: bool,
+    null: bool,
+    array: bool,
+}
 
-impl PartialOrd for EnvPair {
-    fn partial_cmp(&self, other: &EnvPair) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl Default for Infer {
+    #[inline]
+    fn default() -> Self {
+        Self::all()
     }
 }
 
-impl Ord for EnvPair {
-    fn cmp(&self, other: &EnvPair) -> Ordering {
-        self.fields.cmp(&other.fields)
+impl Infer {
+    /// Infer every supported type.
+    #[inline]
+    pub fn all() -> Self {
+        Self {
+            bool: true,
+            integer: true,
+            float: true,
+            null: true,
+            array: true,
+        }
+    }
+
+    /// Every value is a string.
+    #[inline]
+    pub fn none() -> Self {
+        Self {
+            bool: false,
+            integer: false,
+            float: false,
+            null: false,
+            array: false,
+        }
+    }
+
+    /// `true` & `false`.
+    #[inline]
+    pub fn bool(mut self, infer: bool) -> Self {
+        self.bool = infer;
+        self
+    }
+
+    /// `10` & `-10`.
+    #[inline]
+    pub fn integer(mut self, infer: bool) -> Self {
+        self.integer = infer;
+        self
+    }
+
+    /// `1.5` & `1e3`.
+    #[inline]
+    pub fn float(mut self, infer: bool) -> Self {
+        self.float = infer;
+        self
+    }
+
+    /// `null` (as written by `Serializer::serialize_unit`).
+    #[inline]
+    pub fn null(mut self, infer: bool) -> Self {
+        self.null = infer;
+        self
+    }
+
+    /// Inline array (`[a,b]` or value that contains [`Options::array_sep`])
+    /// & indexed array branch (`SERVERS__0`).
+    #[inline]
+    pub fn array(mut self, infer: bool) -> Self {
+        self.array = infer;
+        self
     }
 }
 
-/// This abstraction give us contract that
-/// if the parent not exists, we could create only the branch
-/// This save us to do combinatoric search for a parent sets
-/// in the segment
+/// How malformed lines (or variables) are being handled.
 ///
-/// the parent shouldn't be empty even it's first node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Fails on the first malformed line.
+    Strict,
+    /// Skips malformed lines, and collects them as [`Envs::warnings`].
+    Lenient,
+}
+
+impl Default for Mode {
+    #[inline]
+    fn default() -> Self {
+        Self::Strict
+    }
+}
+
+impl Mode {
+    #[inline]
+    fn report(
+        self,
+        e: DeserializeError,
+        warnings: &mut Vec<DeserializeError>,
+    ) -> Result<(), DeserializeError> {
+        match self {
+            Self::Strict => Err(e),
+            Self::Lenient => {
+                warnings.push(e);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Expansion of `${NAME}` references in values.
 ///
-/// [0, 1, 2] -> Segment { parent : [0, 1], branch: [2] }
+/// - `${NAME}` is the value of `NAME` or empty when it is unset
+/// - `${NAME:-default}` is `default` when `NAME` is unset or empty
+/// - `${NAME:?message}` fails with `message` when `NAME` is unset or empty
+/// - `$$` is a literal `$` (other `$` are kept as is)
 ///
-/// we could savely said that if hole is exists, then create a new node based on it.
-/// when traversing Segment will gradually move hole to parent.
+/// `NAME` is the full key (before the prefix is stripped) of a pair, only the
+/// last pair before the reference is used, pairs after it are never seen.
+/// Values of referred pairs are expanded as well (default & message too).
+/// Single quoted values are taken literally.
 ///
-/// example:
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolate {
+    /// Values are taken as is.
+    Off,
+    /// References are expanded against keys of the same source.
+    Source,
+    /// References are expanded against keys of the same source,
+    /// falls back to the process environment.
+    Env,
+}
+
+impl Default for Interpolate {
+    #[inline]
+    fn default() -> Self {
+        Self::Off
+    }
+}
+
+/// Options for building [`Envs`].
 ///
-/// [[0, 1, 2], [0, 1, 3], [0, 1, 4, 5], [0, 1, 4, 6], [0, 1, 7, 8], [0, 1, 7, 9], [0, 1, 7, 10], [0, 11, 12, 13]]
+/// ```
+/// use envit::de::{Conflict, Options};
 ///
-/// hole: [0, 1], parent: [], leaf: 2
-/// hole: [], parent: [0, 1], leaf: 3
-/// hole: [], parent: [0, 1, 4], leaf: 6
-/// hole: [7], parent: [0, 1], leaf: 8
-/// hole: [], parent: [0, 1, 7], leaf: 9
-/// hole: [], parent: [0, 1, 7], leaf: 10
-/// hole: [11, 12], parent: [0], leaf: 13
+/// let options = Options::default()
+///     .prefix("CONFIG")
+///     .conflict(Conflict::PreferBranch);
+/// ```
 ///
 #[derive(Debug, Clone)]
-pub(crate) struct Segment {
-    hole: Vec<usize>,
-    parent: Vec<usize>,
-    leaf: usize,
+pub struct Options {
+    comment: char,
+    kv_sep: char,
+    key_sep: String,
+    prefix: Option<String>,
+    conflict: Conflict,
+    duplicate: Duplicate,
+    mode: Mode,
+    interpolate: Interpolate,
+    collect_errors: bool,
+    order: Order,
+    tag: String,
+    scalar: ScalarOptions,
 }
 
-#[derive(Debug, Clone)]
-pub struct Envs<'a> {
-    reverse: Vec<&'a str>,
-    indices: Vec<Segment>,
-    data: Vec<&'a str>,
+/// Part of [`Options`] that is needed to read a [`Scalar`].
+#[derive(Debug, Clone, Copy)]
+struct ScalarOptions {
+    sep: char,
+    case: Case,
+    empty: Empty,
+    infer: Infer,
 }
 
-impl<'a> Default for Envs<'a> {
+impl Default for ScalarOptions {
     #[inline]
     fn default() -> Self {
         Self {
-            reverse: Vec::with_capacity(0),
-            indices: Vec::with_capacity(0),
-            data: Vec::with_capacity(0),
+            sep: DEFAULT_ARRAY_SEP,
+            case: Case::default(),
+            empty: Empty::default(),
+            infer: Infer::default(),
         }
     }
 }
 
-impl<'a> Envs<'a> {
+impl Default for Options {
     #[inline]
-    pub fn from_str<'c>(
-        raw: &'c str,
-        comment: char,
-        kv_sep: char,
-        key_sep: &str,
-    ) -> Result<Self, ()> {
-        Self::from_reader(Cursor::new(raw), comment, kv_sep, key_sep)
+    fn default() -> Self {
+        Self {
+            comment: DEFAULT_COMMENT,
+            kv_sep: DEFAULT_KV_SEP,
+            key_sep: DEFAULT_KEY_SEP.to_string(),
+            prefix: None,
+            conflict: Conflict::default(),
+            duplicate: Duplicate::default(),
+            mode: Mode::default(),
+            interpolate: Interpolate::default(),
+            collect_errors: false,
+            order: Order::default(),
+            tag: DEFAULT_TAG.to_string(),
+            scalar: ScalarOptions::default(),
+        }
+    }
+}
+
+impl Options {
+    /// Character that marks a line as comment (default `#`).
+    #[inline]
+    pub fn comment(mut self, comment: char) -> Self {
+        self.comment = comment;
+        self
+    }
+
+    /// Separator between key & value (default `=`).
+    #[inline]
+    pub fn kv_sep(mut self, kv_sep: char) -> Self {
+        self.kv_sep = kv_sep;
+        self
+    }
+
+    /// Separator between each fields in key (default `__`).
+    #[inline]
+    pub fn key_sep(mut self, key_sep: &str) -> Self {
+        self.key_sep = key_sep.to_string();
+        self
+    }
+
+    /// Only keep keys under `prefix` and strip it from the key.
+    ///
+    /// `prefix` may contain several fields (`APP__CONFIG`).
+    ///
+    #[inline]
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.trim_end_matches(self.key_sep.as_str()).to_string());
+        self
+    }
+
+    /// Policy for key that is both a leaf & a branch (default [`Conflict::Error`]).
+    #[inline]
+    pub fn conflict(mut self, conflict: Conflict) -> Self {
+        self.conflict = conflict;
+        self
+    }
+
+    /// Policy for pairs that have the same key (default [`Duplicate::LastWins`]).
+    #[inline]
+    pub fn duplicate(mut self, duplicate: Duplicate) -> Self {
+        self.duplicate = duplicate;
+        self
+    }
+
+    /// How malformed lines are being handled (default [`Mode::Strict`]).
+    #[inline]
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Expansion of `${NAME}` references in values (default [`Interpolate::Off`]).
+    #[inline]
+    pub fn interpolate(mut self, interpolate: Interpolate) -> Self {
+        self.interpolate = interpolate;
+        self
+    }
+
+    /// Keep going after missing or invalid fields when deserializing,
+    /// and report all of them at once (default `false`).
+    ///
+    /// See [`Envs::deserialize`].
+    ///
+    #[inline]
+    pub fn collect_errors(mut self, collect: bool) -> Self {
+        self.collect_errors = collect;
+        self
+    }
+
+    /// Separator between each element in inline array value (default `,`).
+    ///
+    /// See [`Scalar`] for the inline array syntax.
+    ///
+    #[inline]
+    pub fn array_sep(mut self, sep: char) -> Self {
+        self.scalar.sep = sep;
+        self
+    }
+
+    /// Order of sibling nodes (default [`Order::Sorted`]).
+    #[inline]
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Field that names the variant of internally tagged enum (default `TYPE`).
+    ///
+    /// `CREDENTIAL__TYPE=password` selects `password` variant of `credential`
+    /// and the rest of `CREDENTIAL__*` fields become the variant content.
+    ///
+    #[inline]
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tag = tag.to_string();
+        self
+    }
+
+    /// How fields & variants are being matched (default [`Case::Insensitive`]).
+    #[inline]
+    pub fn case(mut self, case: Case) -> Self {
+        self.scalar.case = case;
+        self
+    }
+
+    /// Types that are inferred from value text (default [`Infer::all`]).
+    #[inline]
+    pub fn infer(mut self, infer: Infer) -> Self {
+        self.scalar.infer = infer;
+        self
     }
 
-    pub fn from_reader<'c, R>(
-        reader: R,
+    /// Policy for empty value of an `Option` field (default [`Empty::None`]).
+    #[inline]
+    pub fn empty(mut self, empty: Empty) -> Self {
+        self.scalar.empty = empty;
+        self
+    }
+
+    /// Strip prefix fields from `pair`, returns `None` when the pair is
+    /// not under the prefix.
+    ///
+    fn scope(&self, mut pair: EnvPair) -> Option<EnvPair> {
+        if let Some(prefix) = &self.prefix {
+            let prefix = prefix.split(self.key_sep.as_str()).collect::<Vec<_>>();
+
+            if pair.fields.len() <= prefix.len()
+                || !pair.fields.iter().zip(prefix.iter()).all(|(l, r)| l == r)
+            {
+                return None;
+            }
+
+            pair.fields.drain(..prefix.len());
+        }
+
+        Some(pair)
+    }
+}
+
+/// Equality & ordering of env pair only consider `fields`,
+/// `line` is the (1-based) position of the pair in its source.
+/// `literal` value (single quoted) is never interpolated, while
+/// `quoted` value is never an empty value of `Option` (see [`Empty`]).
+///
+#[derive(Debug, Clone)]
+pub(crate) struct EnvPair {
+    fields: Vec<String>,
+    value: String,
+    line: usize,
+    literal: bool,
+    quoted: bool,
+}
+
+impl EnvPair {
+    /// Parse a single line of env pair, `line` may span multiple lines
+    /// when the value is quoted (`number` is the number of its first line).
+    ///
+    /// Returns `Ok(None)` for empty line or comment, and fails with
+    /// [`DeserializeError::Parse`] when the line is not a valid env pair.
+    ///
+    pub fn from_str(
+        line: &str,
         comment: char,
         kv_sep: char,
         key_sep: &str,
-    ) -> Result<Self, ()>
-    where
-        R: BufRead,
-    {
-        let data: BinaryHeap<EnvPair> = BinaryHeap::from_iter(reader.lines().filter_map(|r| {
-            r.ok()
-                .as_ref()
-                .and_then(move |line| EnvPair::from_str(line, comment, kv_sep, key_sep))
-        }));
+        number: usize,
+    ) -> Result<Option<Self>, DeserializeError> {
+        let trimmed = line.trim();
 
-        let mut inner = Self::default();
-        let mut reverse_idx = HashMap::<String, usize>::new();
+        if trimmed.is_empty() || trimmed.starts_with(comment) {
+            return Ok(None);
+        }
 
-        //
-        // env pair fields will always direct to a leaf node in the branch/tree
-        // so, the only possible parent node would be fields[0-k-1] or
-        // any subsequences fields that exists in the parent sets
-        //
-        // logic:
-        // sets : {}
-        // fields : [0 ... ii]
-        //
-        // sets : {[0 ... ii-1]}
-        // fields : [0 ... ii-1 ... k]
-        //
-        // sets : {[0 ... ii-1], [ii-1 ... k-1], [0 ... k-1]}
-        //
-        // example:
-        // [[0, 1, 2], [0, 1, 3], [0, 1, 4, 5], [0, 1, 4, 6], [0, 1, 7, 8], [0, 1, 7, 9], [0, 1, 7, 10], [0, 11, 12, 13]]
-        //
-        let mut parents = HashSet::<Vec<usize>>::new();
+        // `idx` is 0-based index (in chars) in `line`
+        let parse_error = |idx: usize, reason| {
+            let before = line.chars().take(idx).collect::<String>();
+            let offset = before.matches('\n').count();
+            let column = before
+                .rsplit('\n')
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .count()
+                + 1;
 
-        let mut idx = 0;
-        for EnvPair { fields, value } in data.iter() {
-            let mut indices: Vec<usize> = Vec::new();
+            DeserializeError::Parse {
+                line: number + offset,
+                column,
+                text: line.split('\n').nth(offset).unwrap_or_default().to_string(),
+                reason,
+            }
+        };
 
-            for field in fields {
-                let ridx = match reverse_idx.get(field) {
-                    Some(ridx) => *ridx,
-                    _ => {
-                        let old_idx = idx;
-                        reverse_idx.insert(field.to_string(), idx);
-                        inner.reverse.push(field);
-                        idx += 1;
-                        old_idx
-                    }
-                };
+        // index of the first char of trimmed line
+        let start = line[..line.len() - line.trim_start().len()].chars().count();
+
+        // shell syntax (`export KEY=value`)
+        let (body, exported) = match trimmed.strip_prefix(EXPORT) {
+            Some(rest) if rest.starts_with(char::is_whitespace) => (rest.trim_start(), true),
+            _ => (trimmed, false),
+        };
+
+        // index of the first char of key
+        let start = start + trimmed[..trimmed.len() - body.len()].chars().count();
+
+        let pair = body
+            .splitn(2, kv_sep)
+            .map(move |line| line.to_string())
+            .collect::<Vec<_>>();
+
+        match &pair[..] {
+            [key, value] => {
+                let idx = start + key.chars().count() + 1;
+                let (value, quote) = lex_value(value, comment)
+                    .map_err(|(offset, reason)| parse_error(idx + offset, reason))?;
+
+                // whitespaces around key value separator are allowed
+                let key = key.trim_end();
+
+                if let Some(offset) = key.find(char::is_whitespace) {
+                    let idx = start + key[..offset].chars().count();
+                    return Err(parse_error(idx, "whitespace in key"));
+                }
+
+                let mut pair = Self::new(key, value, key_sep, number);
+                let mut idx = start;
 
-                indices.push(ridx);
+                pair.literal = quote == Some('\'');
+                pair.quoted = quote.is_some();
+
+                for field in pair.fields.iter() {
+                    if field.is_empty() {
+                        return Err(parse_error(idx, "empty field in key"));
+                    }
 
-                if !parents.contains(indices.as_slice()) {
-                    parents.insert(indices.clone());
+                    idx += field.chars().count() + key_sep.chars().count();
                 }
+
+                Ok(Some(pair))
             }
+            // `export KEY` only exports existing variable in shell
+            [key] if exported && !key.contains(char::is_whitespace) => Ok(None),
+            _ => Err(parse_error(
+                start + trimmed.chars().count(),
+                "missing key value separator",
+            )),
+        }
+    }
+
+    #[inline]
+    pub fn new(key: &str, value: String, key_sep: &str, line: usize) -> Self {
+        let fields = key
+            .split(key_sep)
+            .map(move |line| line.to_string())
+            .collect::<Vec<_>>();
 
-            // do combinatoric checks for each leftmost subsequences &
+        Self {
+            fields,
+            value,
+            line,
+            literal: false,
+            quoted: false,
         }
+    }
+}
+
+/// Optional keyword before key, so that env file could be sourced by shell.
+const EXPORT: &str = "export";
+
+/// Reason of [`DeserializeError::Parse`] for quoted value without closing quote.
+const UNTERMINATED: &str = "unterminated quoted value";
 
-        Err(())
+/// Lex value part of env pair (after key value separator), following
+/// common dotenv implementations :
+/// - `"..."` supports `\n`, `\r`, `\t`, `\"` & `\\` escapes
+///   (other escapes are kept as is)
+/// - `'...'` is taken literally
+/// - quoted value may span multiple lines
+/// - unquoted value ends before inline comment, `comment` char
+///   that is preceded by whitespace (`KEY=value # comment`), except inside
+///   quoted element of bracketed inline array (`KEY=["a #b",c] # comment`)
+/// - whitespaces around the value (outside quotes) are trimmed
+///
+/// Returns the value & its quote (if any), fails with
+/// the (0-based, in chars) offset of the problem & its reason.
+///
+fn lex_value(raw: &str, comment: char) -> Result<(String, Option<char>), (usize, &'static str)> {
+    let text = raw.trim_start();
+    let offset = raw[..raw.len() - text.len()].chars().count();
+
+    let quote = match text.chars().next() {
+        Some(quote @ '"') | Some(quote @ '\'') => quote,
+        _ => {
+            let array = text.starts_with('[');
+            let mut prev = raw[..raw.len() - text.len()].chars().last();
+
+            // quote of the current array element, it only opens at
+            // the start of an element (the same as `Elements`).
+            let mut element: Option<char> = None;
+            let mut escaped = false;
+
+            let end = text
+                .char_indices()
+                .find(|&(_, c)| {
+                    let found = match element {
+                        Some(_) if escaped => {
+                            escaped = false;
+                            false
+                        }
+                        Some('"') if c == '\\' => {
+                            escaped = true;
+                            false
+                        }
+                        Some(quote) => {
+                            if c == quote {
+                                element = None;
+                            }
+                            false
+                        }
+                        None if array
+                            && (c == '"' || c == '\'')
+                            && !prev.is_some_and(char::is_alphanumeric) =>
+                        {
+                            element = Some(c);
+                            false
+                        }
+                        None => c == comment && prev.is_some_and(char::is_whitespace),
+                    };
+
+                    prev = Some(c);
+                    found
+                })
+                .map_or(text.len(), |(idx, _)| idx);
+
+            return Ok((text[..end].trim_end().to_string(), None));
+        }
+    };
+
+    let mut value = String::new();
+    let mut chars = text.char_indices().skip(1);
+    let mut closed = None;
+
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            c if c == quote => {
+                closed = Some(idx + c.len_utf8());
+                break;
+            }
+            '\\' if quote == '"' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 'r')) => value.push('\r'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, c @ '"')) | Some((_, c @ '\\')) => value.push(c),
+                Some((_, c)) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+                None => value.push('\\'),
+            },
+            c => value.push(c),
+        }
+    }
+
+    let rest = match closed {
+        Some(end) => &text[end..],
+        None => return Err((offset, UNTERMINATED)),
+    };
+
+    let trailing = rest.trim_start();
+
+    if trailing.is_empty() || trailing.starts_with(comment) {
+        Ok((value, Some(quote)))
+    } else {
+        let column = raw.len() - trailing.len();
+        Err((
+            raw[..column].chars().count(),
+            "unexpected text after quoted value",
+        ))
+    }
+}
+
+impl PartialEq for EnvPair {
+    #[inline]
+    fn eq(&self, other: &EnvPair) -> bool {
+        self.fields.eq(&other.fields)
+    }
+}
+
+impl Eq for EnvPair {}
+
+impl PartialOrd for EnvPair {
+    fn partial_cmp(&self, other: &EnvPair) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compare `fields` segment by segment, see [`compare_field`].
+impl Ord for EnvPair {
+    fn cmp(&self, other: &EnvPair) -> Ordering {
+        self.fields
+            .iter()
+            .zip(other.fields.iter())
+            .map(|(lhs, rhs)| compare_field(lhs, rhs))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| self.fields.len().cmp(&other.fields.len()))
+    }
+}
+
+/// Parse `field` as index of indexed array, only canonical
+/// number is an index (`01` is a field).
+///
+#[inline]
+fn parse_index(field: &str) -> Option<usize> {
+    match field.parse::<usize>() {
+        Ok(index) if field == index.to_string() => Some(index),
+        _ => None,
+    }
+}
+
+/// Index fields are ordered numerically (`2` before `10`) and
+/// placed before other fields, the rest are ordered lexicographically.
+///
+#[inline]
+fn compare_field(lhs: &str, rhs: &str) -> Ordering {
+    match (parse_index(lhs), parse_index(rhs)) {
+        (Some(lhs), Some(rhs)) => lhs.cmp(&rhs),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => lhs.cmp(rhs),
+    }
+}
+
+/// Resolver of `${NAME}` references for [`Envs::interpolate`].
+///
+/// `resolved` memoizes expanded values by index of `pairs`, references only
+/// look at earlier pairs thus expansion always terminates.
+///
+struct Interpolator<'a> {
+    pairs: &'a [EnvPair],
+    keys: Vec<String>,
+    index: HashMap<String, Vec<usize>>,
+    resolved: Vec<Option<String>>,
+    env: bool,
+}
+
+impl<'a> Interpolator<'a> {
+    fn new(pairs: &'a [EnvPair], key_sep: &str, env: bool) -> Self {
+        let keys = pairs
+            .iter()
+            .map(|pair| pair.fields.join(key_sep))
+            .collect::<Vec<_>>();
+
+        let mut index = HashMap::<String, Vec<usize>>::new();
+
+        for (idx, key) in keys.iter().enumerate() {
+            index.entry(key.clone()).or_default().push(idx);
+        }
+
+        Self {
+            pairs,
+            keys,
+            index,
+            resolved: vec![None; pairs.len()],
+            env,
+        }
+    }
+
+    /// Expanded value of pair `idx`.
+    fn value(&mut self, idx: usize) -> Result<String, DeserializeError> {
+        if let Some(value) = &self.resolved[idx] {
+            return Ok(value.clone());
+        }
+
+        let pair = self.pairs[idx].clone();
+
+        let value = if pair.literal {
+            pair.value
+        } else {
+            self.expand(idx, &pair.value)?
+        };
+
+        self.resolved[idx] = Some(value.clone());
+        Ok(value)
+    }
+
+    /// Value of `name` as seen from pair `idx`, `None` when it is unset.
+    fn lookup(&mut self, idx: usize, name: &str) -> Result<Option<String>, DeserializeError> {
+        let found = self
+            .index
+            .get(name)
+            .and_then(|candidates| candidates.iter().rev().find(|&&other| other < idx).copied());
+
+        match found {
+            Some(other) => self.value(other).map(Some),
+            None if self.env => Ok(env::var(name).ok()),
+            None => Ok(None),
+        }
+    }
+
+    /// Expand every reference in `text` (a part of the value of pair `idx`).
+    fn expand(&mut self, idx: usize, text: &str) -> Result<String, DeserializeError> {
+        let (key, line) = (self.keys[idx].clone(), self.pairs[idx].line);
+        let error = |reason: String| DeserializeError::Interpolation {
+            key: key.clone(),
+            line,
+            reason,
+        };
+
+        let mut value = String::new();
+        let mut rest = text;
+
+        while let Some(pos) = rest.find('$') {
+            value.push_str(&rest[..pos]);
+            rest = &rest[pos..];
+
+            if let Some(after) = rest.strip_prefix("$$") {
+                value.push('$');
+                rest = after;
+                continue;
+            }
+
+            let inner = match rest.strip_prefix("${") {
+                Some(inner) => inner,
+                None => {
+                    value.push('$');
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+
+            // closing brace of the reference, skipping nested references
+            let mut depth = 0;
+            let end = inner.char_indices().find_map(|(idx, c)| match c {
+                '{' => {
+                    depth += 1;
+                    None
+                }
+                '}' if depth == 0 => Some(idx),
+                '}' => {
+                    depth -= 1;
+                    None
+                }
+                _ => None,
+            });
+
+            let end = match end {
+                Some(end) => end,
+                None => return Err(error(format!("unterminated reference `{}`", rest))),
+            };
+
+            let reference = &inner[..end];
+            rest = &inner[end + 1..];
+
+            let (name, form) = match reference.find(':') {
+                Some(pos) => (&reference[..pos], Some(&reference[pos + 1..])),
+                None => (reference, None),
+            };
+
+            if name.is_empty() {
+                return Err(error(format!("empty reference `${{{}}}`", reference)));
+            }
+
+            let found = self.lookup(idx, name)?;
+            let set = found.as_deref().is_some_and(|found| !found.is_empty());
+
+            match form {
+                None => value.push_str(&found.unwrap_or_default()),
+                Some(form) if set && (form.starts_with('-') || form.starts_with('?')) => {
+                    value.push_str(&found.unwrap_or_default())
+                }
+                Some(form) if form.starts_with('-') => {
+                    let default = self.expand(idx, &form[1..])?;
+                    value.push_str(&default);
+                }
+                Some(form) if form.starts_with('?') => {
+                    let message = self.expand(idx, &form[1..])?;
+                    let reason = if message.is_empty() {
+                        format!("`{}` is not set", name)
+                    } else {
+                        format!("`{}` is not set: {}", name, message)
+                    };
+
+                    return Err(error(reason));
+                }
+                Some(_) => return Err(error(format!("invalid reference `${{{}}}`", reference))),
+            }
+        }
+
+        value.push_str(rest);
+        Ok(value)
+    }
+}
+
+/// This abstraction give us contract that
+/// if the parent not exists, we could create only the branch
+/// This save us to do combinatoric search for a parent sets
+/// in the segment
+///
+/// the parent shouldn't be empty even it's first node.
+///
+/// ```text
+/// [0, 1, 2] -> Segment { parent : [0, 1], branch: [2] }
+/// ```
+///
+/// we could savely said that if hole is exists, then create a new node based on it.
+/// when traversing Segment will gradually move hole to parent.
+///
+/// example:
+///
+/// ```text
+/// [[0, 1, 2], [0, 1, 3], [0, 1, 4, 5], [0, 1, 4, 6], [0, 1, 7, 8], [0, 1, 7, 9], [0, 1, 7, 10], [0, 11, 12, 13]]
+///
+/// hole: [0, 1], parent: [], leaf: 2
+/// hole: [], parent: [0, 1], leaf: 3
+/// hole: [4], parent: [0, 1], leaf: 5
+/// hole: [], parent: [0, 1, 4], leaf: 6
+/// hole: [7], parent: [0, 1], leaf: 8
+/// hole: [], parent: [0, 1, 7], leaf: 9
+/// hole: [], parent: [0, 1, 7], leaf: 10
+/// hole: [11, 12], parent: [0], leaf: 13
+/// ```
+///
+#[derive(Debug, Clone)]
+pub(crate) struct Segment {
+    hole: Vec<usize>,
+    parent: Vec<usize>,
+    leaf: usize,
+}
+
+/// Node in the tree arena of [`Envs`].
+///
+/// `field` is an index into `Envs::reverse` (`None` only for root node) and
+/// `value` is an index into `Envs::data` (`None` for pure branch node).
+/// `parent` & `children` are indices into `Envs::nodes`.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    field: Option<usize>,
+    value: Option<usize>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+impl Entry {
+    #[inline]
+    fn new(field: Option<usize>, parent: Option<usize>) -> Self {
+        Self {
+            field,
+            value: None,
+            parent,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Tree representation of env pairs.
+///
+/// Each distinct key segment are stored once in `reverse`, and each env pair
+/// becomes a leaf node in `nodes` with its value, source line & whether it's
+/// quoted being stored in `data`, `lines` & `quoted` (at the same position).
+///
+#[derive(Debug)]
+pub struct Envs {
+    reverse: Vec<String>,
+    data: Vec<String>,
+    lines: Vec<usize>,
+    quoted: Vec<bool>,
+    nodes: Vec<Entry>,
+    options: Options,
+    warnings: Vec<DeserializeError>,
+}
+
+impl Default for Envs {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            reverse: Vec::with_capacity(0),
+            data: Vec::with_capacity(0),
+            lines: Vec::with_capacity(0),
+            quoted: Vec::with_capacity(0),
+            nodes: vec![Entry::new(None, None)],
+            options: Options::default(),
+            warnings: Vec::with_capacity(0),
+        }
+    }
+}
+
+impl Envs {
+    /// Index of root node in the tree arena.
+    pub(crate) const ROOT: usize = 0;
+
+    #[inline]
+    pub fn from_str(raw: &str, options: Options) -> Result<Self, DeserializeError> {
+        Self::from_reader(Cursor::new(raw), options)
+    }
+
+    pub fn from_reader<R>(reader: R, options: Options) -> Result<Self, DeserializeError>
+    where
+        R: BufRead,
+    {
+        let Options {
+            comment,
+            kv_sep,
+            ref key_sep,
+            ..
+        } = options;
+
+        let mut data = Vec::new();
+        let mut warnings = Vec::new();
+
+        // quoted value that is not closed yet (number of its first line & its text),
+        // the following lines are part of the value until the closing quote.
+        let mut pending: Option<(usize, String)> = None;
+
+        // split by bytes instead of `BufRead::lines` so that invalid utf-8
+        // could be reported (with its line) without stopping the reader.
+        for (idx, chunk) in reader.split(b'\n').enumerate() {
+            let number = idx + 1;
+            let mut bytes = chunk?;
+
+            if bytes.last() == Some(&b'\r') {
+                bytes.pop();
+            }
+
+            let line = String::from_utf8(bytes).map_err(|e| {
+                let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+
+                DeserializeError::Parse {
+                    line: number,
+                    column: String::from_utf8_lossy(valid).chars().count() + 1,
+                    text: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                    reason: "invalid utf-8",
+                }
+            });
+
+            let (number, line) = match (pending.take(), line) {
+                (Some((first, mut text)), Ok(line)) => {
+                    text.push('\n');
+                    text.push_str(&line);
+                    (first, text)
+                }
+                (_, Ok(line)) => (number, line),
+                (_, Err(e)) => {
+                    options.mode.report(e, &mut warnings)?;
+                    continue;
+                }
+            };
+
+            match EnvPair::from_str(&line, comment, kv_sep, key_sep, number) {
+                Ok(pair) => data.extend(pair),
+                Err(DeserializeError::Parse { reason, .. }) if reason == UNTERMINATED => {
+                    pending = Some((number, line));
+                }
+                Err(e) => options.mode.report(e, &mut warnings)?,
+            }
+        }
+
+        // closing quote is missing, the error points at the opening quote
+        if let Some((number, line)) = pending {
+            if let Err(e) = EnvPair::from_str(&line, comment, kv_sep, key_sep, number) {
+                options.mode.report(e, &mut warnings)?;
+            }
+        }
+
+        Self::from_pairs(data, warnings, options)
+    }
+
+    /// Build envs from snapshot of the process environment.
+    ///
+    /// Only variables under [`Options::prefix`] (when given) are kept and the prefix
+    /// (including the key separator after it) is stripped from the key, so
+    /// `CONFIG__DATABASE__POOL` with prefix `CONFIG` becomes `DATABASE__POOL`.
+    /// Variables (under the prefix) that are not valid unicode are
+    /// being reported based on [`Options::mode`].
+    ///
+    #[inline]
+    pub fn from_env(options: Options) -> Result<Self, DeserializeError> {
+        Self::from_vars(env::vars_os(), options)
+    }
+
+    /// Build envs from arbitrary `(key, value)` variables.
+    ///
+    /// See [`Envs::from_env`] for how prefix is being handled.
+    ///
+    pub fn from_vars<I, K, V>(vars: I, options: Options) -> Result<Self, DeserializeError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<OsString>,
+        V: Into<OsString>,
+    {
+        let mut data = Vec::new();
+        let mut warnings = Vec::new();
+
+        for (idx, (key, value)) in vars.into_iter().enumerate() {
+            let number = idx + 1;
+
+            match (key.into().into_string(), value.into().into_string()) {
+                (Ok(key), Ok(value)) => {
+                    data.push(EnvPair::new(&key, value, &options.key_sep, number))
+                }
+                (key, value) => {
+                    let key = key.unwrap_or_else(|key| key.to_string_lossy().into_owned());
+                    let value = value.unwrap_or_else(|value| value.to_string_lossy().into_owned());
+                    let text = format!("{}{}{}", key, options.kv_sep, value);
+
+                    if options
+                        .scope(EnvPair::new(&key, value, &options.key_sep, number))
+                        .is_some()
+                    {
+                        let e = DeserializeError::Parse {
+                            line: number,
+                            column: 1,
+                            text,
+                            reason: "invalid unicode",
+                        };
+
+                        options.mode.report(e, &mut warnings)?;
+                    }
+                }
+            }
+        }
+
+        Self::from_pairs(data, warnings, options)
+    }
+
+    /// Sort `data` by first appearance of each fields prefix, so that pairs
+    /// under the same parent stay together while siblings keep the source order.
+    ///
+    fn sort_by_source(data: &mut Vec<EnvPair>) {
+        let mut appearance = HashMap::<&[String], usize>::new();
+
+        for pair in data.iter() {
+            for depth in 1..=pair.fields.len() {
+                let next = appearance.len();
+                appearance.entry(&pair.fields[..depth]).or_insert(next);
+            }
+        }
+
+        let ranks = data
+            .iter()
+            .map(|pair| {
+                (1..=pair.fields.len())
+                    .map(|depth| appearance[&pair.fields[..depth]])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut order = (0..data.len()).collect::<Vec<_>>();
+
+        order.sort_by(|&lhs, &rhs| {
+            let (l, r) = (&data[lhs], &data[rhs]);
+
+            l.fields
+                .iter()
+                .zip(r.fields.iter())
+                .zip(ranks[lhs].iter().zip(ranks[rhs].iter()))
+                .map(
+                    |((lf, rf), (lr, rr))| match (parse_index(lf), parse_index(rf)) {
+                        (None, None) => lr.cmp(rr),
+                        _ => compare_field(lf, rf),
+                    },
+                )
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or_else(|| l.fields.len().cmp(&r.fields.len()))
+        });
+
+        let mut pairs = data.drain(..).map(Some).collect::<Vec<_>>();
+        data.extend(order.into_iter().filter_map(|idx| pairs[idx].take()));
+    }
+
+    /// Expand references in values of `data` (in source order),
+    /// see [`Interpolate`] for the syntax.
+    ///
+    /// Failed values are being reported based on [`Options::mode`]
+    /// (they are kept as is in lenient mode).
+    ///
+    fn interpolate(
+        data: &mut [EnvPair],
+        options: &Options,
+        warnings: &mut Vec<DeserializeError>,
+    ) -> Result<(), DeserializeError> {
+        let env = match options.interpolate {
+            Interpolate::Off => return Ok(()),
+            Interpolate::Source => false,
+            Interpolate::Env => true,
+        };
+
+        let mut interpolator = Interpolator::new(data, &options.key_sep, env);
+        let values = (0..data.len())
+            .map(|idx| interpolator.value(idx))
+            .collect::<Vec<_>>();
+
+        for (pair, value) in data.iter_mut().zip(values) {
+            match value {
+                Ok(value) => pair.value = value,
+                Err(e) => options.mode.report(e, warnings)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn from_pairs(
+        mut data: Vec<EnvPair>,
+        mut warnings: Vec<DeserializeError>,
+        options: Options,
+    ) -> Result<Self, DeserializeError> {
+        Self::interpolate(&mut data, &options, &mut warnings)?;
+
+        let mut data = data
+            .into_iter()
+            .filter_map(|pair| options.scope(pair))
+            .collect::<Vec<_>>();
+
+        let mut inner = Self {
+            warnings,
+            ..Self::default()
+        };
+        let mut reverse_idx = HashMap::<String, usize>::new();
+
+        //
+        // env pair fields will always direct to a leaf node in the branch/tree
+        // so, the only possible parent node would be fields[0-k-1] or
+        // any subsequences fields that exists in the parent sets
+        //
+        // since the pairs are sorted, the parent of current pair could only be
+        // the leftmost subsequence that current pair shares with the previous pair.
+        // the rest of the fields (except the last one) are the hole.
+        //
+        // `path` keeps the node indices of the previous pair (starting from root),
+        // while `prev` keeps the field indices of the previous pair.
+        //
+        let mut path = vec![Self::ROOT];
+        let mut prev: Vec<usize> = Vec::new();
+
+        // pairs with the same key stay in their source order (stable sort),
+        // so that duplicate policy could pick the first or the last one.
+        let mut duplicates: Vec<(usize, Vec<usize>)> = Vec::new();
+
+        match options.order {
+            Order::Sorted => data.sort(),
+            Order::Source => Self::sort_by_source(&mut data),
+        }
+
+        for EnvPair {
+            fields,
+            value,
+            line,
+            quoted,
+            ..
+        } in data
+        {
+            let mut indices = fields
+                .into_iter()
+                .map(|field| inner.intern(&mut reverse_idx, field))
+                .collect::<Vec<_>>();
+
+            let leaf = match indices.pop() {
+                Some(leaf) => leaf,
+                None => continue,
+            };
+
+            let shared = indices
+                .iter()
+                .zip(prev.iter())
+                .take_while(|(lhs, rhs)| lhs == rhs)
+                .count();
+
+            let segment = Segment {
+                hole: indices[shared..].to_vec(),
+                parent: indices[..shared].to_vec(),
+                leaf,
+            };
+
+            path.truncate(segment.parent.len() + 1);
+
+            for field in segment.hole.iter() {
+                let node = inner.push_node(path[path.len() - 1], *field);
+                path.push(node);
+            }
+
+            let parent = path[path.len() - 1];
+
+            // sorted pairs guarantee that the same leaf could only be
+            // the last child of the parent.
+            let node = match inner.nodes[parent].children.last() {
+                Some(last) if inner.nodes[*last].field == Some(segment.leaf) => *last,
+                _ => inner.push_node(parent, segment.leaf),
+            };
+
+            match (inner.nodes[node].value, options.duplicate) {
+                (None, _) | (Some(_), Duplicate::LastWins) => {
+                    inner.nodes[node].value = Some(inner.data.len());
+                }
+                (Some(_), Duplicate::FirstWins) => (),
+                (Some(first), Duplicate::Error) => match duplicates.last_mut() {
+                    Some((last, lines)) if *last == node => lines.push(line),
+                    _ => duplicates.push((node, vec![inner.lines[first], line])),
+                },
+            }
+
+            inner.data.push(value);
+            inner.lines.push(line);
+            inner.quoted.push(quoted);
+
+            path.push(node);
+            prev = indices;
+            prev.push(leaf);
+        }
+
+        inner.options = options;
+
+        let mut duplicates = duplicates
+            .into_iter()
+            .map(|(node, lines)| DeserializeError::Duplicate {
+                key: Node {
+                    envs: &inner,
+                    id: node,
+                }
+                .key(),
+                lines,
+            })
+            .collect::<Vec<_>>();
+
+        match duplicates.len() {
+            0 => (),
+            1 => return Err(duplicates.remove(0)),
+            _ => return Err(DeserializeError::Multiple(duplicates)),
+        }
+
+        inner.resolve_conflicts(&mut reverse_idx)?;
+
+        Ok(inner)
+    }
+
+    /// Resolve nodes that are both a leaf and a branch
+    /// based on [`Options::conflict`] policy.
+    ///
+    fn resolve_conflicts(
+        &mut self,
+        reverse_idx: &mut HashMap<String, usize>,
+    ) -> Result<(), DeserializeError> {
+        let conflicts = (0..self.nodes.len())
+            .filter(|id| {
+                let entry = &self.nodes[*id];
+                entry.value.is_some() && !entry.children.is_empty()
+            })
+            .collect::<Vec<_>>();
+
+        for id in conflicts {
+            match self.options.conflict {
+                Conflict::Error => {
+                    let node = Node { envs: self, id };
+                    let branch = node
+                        .walk()
+                        .find(|child| child.id != id && child.is_leaf())
+                        .unwrap_or(node);
+
+                    return Err(DeserializeError::Conflict {
+                        leaf: node.key(),
+                        branch: branch.key(),
+                    });
+                }
+                Conflict::PreferBranch => self.nodes[id].value = None,
+                Conflict::PreferLeaf => self.nodes[id].children.clear(),
+                Conflict::ValueEntry => {
+                    let field = self.intern(reverse_idx, VALUE_KEY.to_string());
+                    let child = self.push_node(id, field);
+
+                    // keep `_value` as the first child, the same as
+                    // how the leaf pair was sorted before its branch pairs.
+                    self.nodes[id].children.pop();
+                    self.nodes[id].children.insert(0, child);
+                    self.nodes[child].value = self.nodes[id].value.take();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn intern(&mut self, reverse_idx: &mut HashMap<String, usize>, field: String) -> usize {
+        match reverse_idx.get(&field) {
+            Some(ridx) => *ridx,
+            _ => {
+                let ridx = self.reverse.len();
+                reverse_idx.insert(field.clone(), ridx);
+                self.reverse.push(field);
+                ridx
+            }
+        }
+    }
+
+    #[inline]
+    fn push_node(&mut self, parent: usize, field: usize) -> usize {
+        let node = self.nodes.len();
+        self.nodes.push(Entry::new(Some(field), Some(parent)));
+        self.nodes[parent].children.push(node);
+        node
+    }
+
+    /// Returns the root node of the tree.
+    ///
+    /// Root node doesn't have any name nor value, its children are
+    /// the first fields of every env pair.
+    ///
+    #[inline]
+    pub fn root(&self) -> Node<'_> {
+        Node {
+            envs: self,
+            id: Self::ROOT,
+        }
+    }
+
+    /// Returns all nodes in depth-first (pre-order) order, starting from root.
+    ///
+    #[inline]
+    pub fn walk(&self) -> Walk<'_> {
+        self.root().walk()
+    }
+
+    /// Deserialize an instance of type `T` from the root of this envs.
+    ///
+    /// When [`Options::collect_errors`] is enabled, deserialization keeps going
+    /// after missing or invalid fields and fails with [`DeserializeError::Multiple`]
+    /// listing every error (sorted by key).
+    ///
+    #[inline]
+    pub fn deserialize<'de, T>(&'de self) -> Result<T, DeserializeError>
+    where
+        T: Deserialize<'de>,
+    {
+        let root = self.root();
+
+        if !self.options.collect_errors {
+            return T::deserialize(Deserializer::new(self)).map_err(|e| e.locate(root));
+        }
+
+        let recovery = Shared::default();
+
+        loop {
+            {
+                let mut recovery = recovery.borrow_mut();
+                recovery.errors.clear();
+                recovery.patched = false;
+            }
+
+            let result = T::deserialize(Deserializer {
+                node: root,
+                recovery: Some(recovery.clone()),
+                skip: None,
+            });
+
+            match result {
+                Ok(value) => {
+                    let recovery = recovery.borrow();
+
+                    if recovery.errors.is_empty() && recovery.fatal.is_empty() {
+                        return Ok(value);
+                    }
+
+                    break;
+                }
+                Err(e) => {
+                    // error raised by visitor of root node
+                    if e.path().is_none() {
+                        patch(Some(&recovery), root, e, Patch::Placeholder);
+                    }
+
+                    // no progress since the previous pass
+                    if !recovery.borrow().patched {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut recovery = recovery.borrow_mut();
+        let mut errors = recovery.fatal.drain(..).collect::<Vec<_>>();
+        errors.append(&mut recovery.errors);
+        errors.sort_by(|lhs, rhs| lhs.path().cmp(&rhs.path()));
+
+        Err(DeserializeError::Multiple(errors))
+    }
+
+    /// Problems that were skipped when building this envs in [`Mode::Lenient`].
+    #[inline]
+    pub fn warnings(&self) -> &[DeserializeError] {
+        &self.warnings
+    }
+
+    /// Options that were used to build this envs.
+    #[inline]
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+}
+
+/// Read-only view of a node in [`Envs`] tree.
+///
+/// A node could be a branch (has children), a leaf (has value) or both
+/// (`A=1` and `A__B=2` makes `A` both a branch and a leaf).
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Node<'e> {
+    envs: &'e Envs,
+    id: usize,
+}
+
+impl<'e> Node<'e> {
+    #[inline]
+    fn entry(&self) -> &'e Entry {
+        &self.envs.nodes[self.id]
+    }
+
+    /// Field name of this node, `None` for root node.
+    #[inline]
+    pub fn name(&self) -> Option<&'e str> {
+        let envs = self.envs;
+        self.entry().field.map(|field| envs.reverse[field].as_str())
+    }
+
+    /// Value of this node, `None` if this node is a pure branch.
+    #[inline]
+    pub fn value(&self) -> Option<&'e str> {
+        let envs = self.envs;
+        self.entry().value.map(|value| envs.data[value].as_str())
+    }
+
+    /// Source line (or position of the variable) of this node's value,
+    /// `None` if this node is a pure branch.
+    #[inline]
+    pub fn line(&self) -> Option<usize> {
+        let envs = self.envs;
+        self.entry().value.map(|value| envs.lines[value])
+    }
+
+    /// Whether this node's value is quoted (`KEY=""`).
+    #[inline]
+    pub(crate) fn quoted(&self) -> bool {
+        let envs = self.envs;
+        self.entry().value.is_some_and(|value| envs.quoted[value])
+    }
+
+    /// Parent of this node, `None` for root node.
+    #[inline]
+    pub fn parent(&self) -> Option<Node<'e>> {
+        let envs = self.envs;
+        self.entry().parent.map(|id| Node { envs, id })
+    }
+
+    /// Children of this node, in key order.
+    #[inline]
+    pub fn children(&self) -> Children<'e> {
+        Children {
+            envs: self.envs,
+            inner: self.entry().children.iter(),
+        }
+    }
+
+    #[inline]
+    pub fn is_leaf(&self) -> bool {
+        self.entry().value.is_some()
+    }
+
+    #[inline]
+    pub fn is_branch(&self) -> bool {
+        !self.entry().children.is_empty()
+    }
+
+    /// Field names from the first field until this node.
+    ///
+    /// `CONFIG__DATABASE__NAME` leaf node returns `["CONFIG", "DATABASE", "NAME"]`.
+    ///
+    pub fn path(&self) -> Vec<&'e str> {
+        let mut path = Vec::new();
+        let mut current = Some(*self);
+
+        while let Some(node) = current {
+            if let Some(name) = node.name() {
+                path.push(name);
+            }
+
+            current = node.parent();
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// Full env key of this node, including [`Options::prefix`].
+    ///
+    /// `CONFIG__DATABASE__NAME` leaf node returns `"CONFIG__DATABASE__NAME"`
+    /// even when it was loaded with `CONFIG` prefix.
+    ///
+    pub fn key(&self) -> String {
+        let options = &self.envs.options;
+        options
+            .prefix
+            .iter()
+            .map(String::as_str)
+            .chain(self.path())
+            .collect::<Vec<_>>()
+            .join(&options.key_sep)
+    }
+
+    /// Full env key of `field` under this node.
+    pub(crate) fn join(&self, field: &str) -> String {
+        let key = self.key();
+
+        if key.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}{}{}", key, self.envs.options.key_sep, field)
+        }
+    }
+
+    /// Returns this node & all of its descendants in depth-first (pre-order) order.
+    ///
+    #[inline]
+    pub fn walk(&self) -> Walk<'e> {
+        Walk { stack: vec![*self] }
+    }
+}
+
+/// Iterator over children of a [`Node`].
+///
+#[derive(Debug, Clone)]
+pub struct Children<'e> {
+    envs: &'e Envs,
+    inner: std::slice::Iter<'e, usize>,
+}
+
+impl<'e> Iterator for Children<'e> {
+    type Item = Node<'e>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let envs = self.envs;
+        self.inner.next().map(|id| Node { envs, id: *id })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'e> DoubleEndedIterator for Children<'e> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let envs = self.envs;
+        self.inner.next_back().map(|id| Node { envs, id: *id })
+    }
+}
+
+impl<'e> ExactSizeIterator for Children<'e> {}
+
+/// Depth-first (pre-order) iterator over nodes of [`Envs`].
+///
+#[derive(Debug, Clone)]
+pub struct Walk<'e> {
+    stack: Vec<Node<'e>>,
+}
+
+impl<'e> Iterator for Walk<'e> {
+    type Item = Node<'e>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children().rev());
+        Some(node)
+    }
+}
+
+/// Deserialize an instance of type `T` from env formatted string.
+///
+pub fn from_str<T>(raw: &str) -> Result<T, DeserializeError>
+where
+    T: DeserializeOwned,
+{
+    from_reader(Cursor::new(raw))
+}
+
+/// Deserialize an instance of type `T` from buffered reader of env formatted lines.
+///
+pub fn from_reader<R, T>(reader: R) -> Result<T, DeserializeError>
+where
+    R: BufRead,
+    T: DeserializeOwned,
+{
+    let envs = Envs::from_reader(reader, Options::default())?;
+    envs.deserialize()
+}
+
+/// Deserialize an instance of type `T` from the process environment.
+///
+/// Every variable becomes part of the tree, for scoping variables
+/// under a single key use [`from_env_prefixed`].
+///
+pub fn from_env<T>() -> Result<T, DeserializeError>
+where
+    T: DeserializeOwned,
+{
+    let envs = Envs::from_env(Options::default())?;
+    envs.deserialize()
+}
+
+/// Deserialize an instance of type `T` from process environment variables
+/// under `prefix`.
+///
+/// `from_env_prefixed::<T>("CONFIG")` deserialize `CONFIG__DATABASE__POOL`
+/// into `database.pool` field of `T` (given the field are named `DATABASE` & `POOL`).
+///
+pub fn from_env_prefixed<T>(prefix: &str) -> Result<T, DeserializeError>
+where
+    T: DeserializeOwned,
+{
+    let envs = Envs::from_env(Options::default().prefix(prefix))?;
+    envs.deserialize()
+}
+
+/// Type that drive serde visitors off a node in [`Envs`] tree.
+///
+/// Branch node are presented as map (or struct) while leaf node
+/// are presented as scalar value (see [`Scalar`]).
+///
+/// Enum are read from :
+/// - leaf value for unit variant (`ENV=development`)
+/// - [`Options::tag`] field of a branch for internally tagged variant
+///   (`CREDENTIAL__TYPE=password` & `CREDENTIAL__PASSWORD=secret`)
+/// - the only field of a branch for externally tagged variant
+///   (`CREDENTIAL__PASSWORD__PASSWORD=secret`)
+///
+pub struct Deserializer<'de> {
+    node: Node<'de>,
+    recovery: Option<Shared>,
+    // tag field of internally tagged enum content
+    skip: Option<usize>,
+}
+
+impl<'de> Deserializer<'de> {
+    /// Create deserializer that starts from root node of `envs`.
+    #[inline]
+    pub fn new(envs: &'de Envs) -> Self {
+        Self::from_node(envs.root())
+    }
+
+    /// Create deserializer that starts from given `node`.
+    #[inline]
+    pub fn from_node(node: Node<'de>) -> Self {
+        Self {
+            node,
+            recovery: None,
+            skip: None,
+        }
+    }
+
+    /// Record `e` (when collecting errors) & continue with a [`Placeholder`] value,
+    /// otherwise fails with `e`.
+    ///
+    #[inline]
+    fn recover<V, F>(
+        &self,
+        e: DeserializeError,
+        visitor: V,
+        f: F,
+    ) -> Result<V::Value, DeserializeError>
+    where
+        V: Visitor<'de>,
+        F: FnOnce(Placeholder, V) -> Result<V::Value, DeserializeError>,
+    {
+        recover(
+            self.recovery.as_ref().map(|r| (r, self.node)),
+            e,
+            visitor,
+            f,
+        )
+    }
+
+    #[inline]
+    fn is_branch(&self) -> bool {
+        self.node.is_branch()
+    }
+
+    /// Children of current node ordered by their index, fails when indices
+    /// are not contiguous from `0` or mixed with non index fields.
+    ///
+    fn indexed<V>(&self, visitor: &V) -> Result<Vec<Node<'de>>, DeserializeError>
+    where
+        V: Visitor<'de>,
+    {
+        let mut items = Vec::with_capacity(self.node.children().len());
+        let mut fields = Vec::new();
+
+        for child in self.children() {
+            let name = child.name().unwrap_or_default();
+
+            match parse_index(name) {
+                Some(index) => items.push((index, child)),
+                None => fields.push(name),
+            }
+        }
+
+        match (items.is_empty(), fields.first()) {
+            (true, _) => return Err(de::Error::invalid_type(Unexpected::Map, visitor)),
+            (false, Some(field)) => {
+                return Err(DeserializeError::MixedIndex {
+                    path: None,
+                    field: field.to_string(),
+                })
+            }
+            _ => (),
+        }
+
+        // siblings are ordered numerically (see `compare_field`)
+        match items
+            .iter()
+            .enumerate()
+            .find(|(expected, (index, _))| expected != index)
+        {
+            Some((expected, _)) => Err(DeserializeError::MissingIndex {
+                path: None,
+                index: expected,
+            }),
+            None => Ok(items.into_iter().map(|(_, child)| child).collect()),
+        }
+    }
+
+    /// Children of current node (without the tag field of enum content).
+    #[inline]
+    fn children(&self) -> impl Iterator<Item = Node<'de>> {
+        let skip = self.skip;
+        self.node
+            .children()
+            .filter(move |child| Some(child.id) != skip)
+    }
+
+    /// Node that names the enum variant of current branch, either the
+    /// [`Options::tag`] field (internally tagged, `true`) or the only field
+    /// (externally tagged, `false`).
+    ///
+    fn variant(&self) -> Result<(Node<'de>, bool), DeserializeError> {
+        let options = &self.node.envs.options;
+        let tag = options.tag.as_str();
+
+        let tagged = self.children().find(|child| {
+            child.is_leaf()
+                && options
+                    .scalar
+                    .case
+                    .matches(child.name().unwrap_or_default(), tag)
+        });
+
+        if let Some(child) = tagged {
+            return Ok((child, true));
+        }
+
+        let mut children = self.children();
+
+        match (children.next(), children.next()) {
+            (Some(child), None) => Ok((child, false)),
+            _ => Err(de::Error::invalid_value(
+                Unexpected::Map,
+                &format!("`{}` field or a single variant field", tag).as_str(),
+            )),
+        }
+    }
+
+    /// Returns the leaf value of current node as [`Scalar`],
+    /// `None` if current node doesn't hold any value.
+    ///
+    #[inline]
+    fn scalar(&self) -> Option<Scalar<'de>> {
+        let recovery = self.recovery.clone().map(|r| (r, self.node));
+
+        let options = &self.node.envs.options;
+
+        self.node.value().map(|value| Scalar {
+            value: Cow::Borrowed(value),
+            quoted: self.node.quoted(),
+            options: options.scalar,
+            recovery,
+        })
+    }
+}
+
+macro_rules! forward_to_scalar {
+    ($($method:ident)*) => {
+        $(
+            #[inline]
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                match self.scalar() {
+                    Some(scalar) => scalar.$method(visitor),
+                    None => {
+                        let e = de::Error::invalid_type(Unexpected::Map, &visitor);
+                        self.recover(e, visitor, |p, v| p.$method(v))
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if !self.is_branch() {
+            if let Some(scalar) = self.scalar() {
+                return scalar.deserialize_any(visitor);
+            }
+        }
+
+        // indexed array branch is presented as sequence, other branch as map
+        if self.node.envs.options.scalar.infer.array {
+            if let Ok(items) = self.indexed(&visitor) {
+                return visitor.visit_seq(Items {
+                    items: items.into_iter(),
+                    recovery: self.recovery,
+                });
+            }
+        }
+
+        visitor.visit_map(Fields::new(self.node, self.skip, None, self.recovery))
+    }
+
+    forward_to_scalar! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_identifier
+    }
+
+    /// Branch is being read as indexed array (`SERVERS__0__HOST`, `SERVERS__1__HOST`),
+    /// while leaf is being read as inline array (`10,20,30`).
+    ///
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if !self.is_branch() {
+            return match self.scalar() {
+                Some(scalar) => scalar.deserialize_seq(visitor),
+                // node of empty envs
+                None => visitor.visit_seq(Placeholders(0)),
+            };
+        }
+
+        match self.indexed(&visitor) {
+            Ok(items) => visitor.visit_seq(Items {
+                items: items.into_iter(),
+                recovery: self.recovery,
+            }),
+            Err(e) => self.recover(e, visitor, |p, v| p.deserialize_seq(v)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        // missing fields are handled by serde (as `None`),
+        // while empty value follows `Options::empty`
+        match self.scalar() {
+            Some(scalar) if !self.is_branch() => scalar.deserialize_option(visitor),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    #[inline]
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    #[inline]
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    #[inline]
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    #[inline]
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    #[inline]
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.node.value() {
+            Some(value) if !self.is_branch() => {
+                let e = de::Error::invalid_type(Unexpected::Str(value), &visitor);
+                self.recover(e, visitor, |p, v| p.deserialize_map(v))
+            }
+            _ => visitor.visit_map(Fields::new(self.node, self.skip, None, self.recovery)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.node.value() {
+            Some(value) if !self.is_branch() => {
+                let e = de::Error::invalid_type(Unexpected::Str(value), &visitor);
+                self.recover(e, visitor, |p, v| p.deserialize_struct(name, fields, v))
+            }
+            _ => visitor.visit_map(Fields::new(
+                self.node,
+                self.skip,
+                Some(fields),
+                self.recovery,
+            )),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if !self.is_branch() {
+            return match self.scalar() {
+                Some(scalar) => scalar.deserialize_enum(name, variants, visitor),
+                None => {
+                    let e = de::Error::invalid_type(Unexpected::Map, &visitor);
+                    self.recover(e, visitor, |p, v| p.deserialize_enum(name, variants, v))
+                }
+            };
+        }
+
+        match self.variant() {
+            Ok((variant, _)) if is_placeholder(self.recovery.as_ref(), variant) => {
+                Placeholder.deserialize_enum(name, variants, visitor)
+            }
+            Ok((variant, tagged)) => visitor.visit_enum(Variant {
+                de: self,
+                variant,
+                tagged,
+                variants,
+            }),
+            Err(e) => self.recover(e, visitor, |p, v| p.deserialize_enum(name, variants, v)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+/// Map access over children of a branch node, `names` are the fields of
+/// struct (`None` for map).
+///
+/// Key of each entry is the field name of the child node, while the value
+/// is being deserialized by [`Deserializer`] that starts from the child node.
+/// Struct fields are matched by [`Case::resolve`], while map keys are kept
+/// as written & parsed from the key segment text (`u16` or enum keys).
+///
+/// When collecting errors, fields that were reported missing in the previous
+/// passes are being yielded (after the children) with [`Placeholder`] value.
+///
+struct Fields<'de> {
+    node: Node<'de>,
+    children: Children<'de>,
+    skip: Option<usize>,
+    names: Option<&'static [&'static str]>,
+    current: Option<Node<'de>>,
+    missing: std::vec::IntoIter<&'static str>,
+    recovery: Option<Shared>,
+}
+
+impl<'de> Fields<'de> {
+    #[inline]
+    fn new(
+        node: Node<'de>,
+        skip: Option<usize>,
+        names: Option<&'static [&'static str]>,
+        recovery: Option<Shared>,
+    ) -> Self {
+        let missing = recovery
+            .as_ref()
+            .and_then(|r| r.borrow().missing.get(&node.id).cloned())
+            .unwrap_or_default();
+
+        Self {
+            node,
+            children: node.children(),
+            skip,
+            names,
+            current: None,
+            missing: missing.into_iter(),
+            recovery,
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Fields<'de> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        let recovery = self.recovery.as_ref();
+        let skip = self.skip;
+        let skipped = |child: &Node<'de>| {
+            Some(child.id) == skip
+                || recovery.is_some_and(|r| r.borrow().skipped.contains(&child.id))
+        };
+
+        match self.children.by_ref().find(|child| !skipped(child)) {
+            Some(child) => {
+                self.current = Some(child);
+                let field = child.name().unwrap_or_default();
+                let options = self.node.envs.options.scalar;
+
+                let result = match self.names {
+                    Some(names) => options.case.resolve(field, names).and_then(|resolved| {
+                        seed.deserialize(Scalar::new(resolved.unwrap_or(field)))
+                    }),
+                    None => seed.deserialize(Scalar {
+                        value: Cow::Borrowed(field),
+                        quoted: false,
+                        options: ScalarOptions {
+                            infer: Infer::none(),
+                            ..options
+                        },
+                        recovery: None,
+                    }),
+                };
+
+                result
+                    .map(Some)
+                    .map_err(|e| patch(recovery, child, e, Patch::Skip))
+            }
+            None => match self.missing.next() {
+                Some(field) => {
+                    self.current = None;
+                    seed.deserialize(Scalar::new(field)).map(Some)
+                }
+                None => Ok(None),
+            },
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        match self.current.take() {
+            Some(child) => descend(self.recovery.as_ref(), child, seed),
+            // value of missing field (when collecting errors), the error is
+            // being located so that the parent node isn't patched.
+            None => seed.deserialize(Placeholder).map_err(|e| match e {
+                DeserializeError::MissingField { field, .. } => DeserializeError::MissingField {
+                    path: Some(self.node.join(field)),
+                    field,
+                },
+                e => e.locate(self.node),
+            }),
+        }
+    }
+}
+
+/// Sequence access over indexed array branch (`SERVERS__0`, `SERVERS__1`).
+///
+struct Items<'de> {
+    items: std::vec::IntoIter<Node<'de>>,
+    recovery: Option<Shared>,
+}
+
+impl<'de> de::SeqAccess<'de> for Items<'de> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.items.next() {
+            Some(item) => descend(self.recovery.as_ref(), item, seed).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+/// Enum access over a branch, `variant` is either the tag field (`tagged`)
+/// or the only field of the branch.
+///
+struct Variant<'de> {
+    de: Deserializer<'de>,
+    variant: Node<'de>,
+    tagged: bool,
+    variants: &'static [&'static str],
+}
+
+impl<'de> Variant<'de> {
+    /// Deserializer of the variant content, the branch itself (without
+    /// the tag field) for internally tagged enum.
+    ///
+    #[inline]
+    fn content(self) -> Deserializer<'de> {
+        if self.tagged {
+            Deserializer {
+                skip: Some(self.variant.id),
+                ..self.de
+            }
+        } else {
+            Deserializer {
+                node: self.variant,
+                recovery: self.de.recovery,
+                skip: None,
+            }
+        }
+    }
+
+    /// Locate (and patch) errors that are raised by the visitor
+    /// of externally tagged variant content.
+    ///
+    #[inline]
+    fn locate<T>(
+        variant: Node<'de>,
+        recovery: Option<&Shared>,
+        result: Result<T, DeserializeError>,
+    ) -> Result<T, DeserializeError> {
+        result.map_err(|e| patch(recovery, variant, e, Patch::Placeholder))
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for Variant<'de> {
+    type Error = DeserializeError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let name = if self.tagged {
+            self.variant.value()
+        } else {
+            self.variant.name()
+        };
+
+        let name = name.unwrap_or_default();
+        let recovery = self.de.recovery.as_ref();
+        let case = self.de.node.envs.options.scalar.case;
+
+        let variant = case
+            .resolve(name, self.variants)
+            .and_then(|resolved| seed.deserialize(Scalar::new(resolved.unwrap_or(name))))
+            .map_err(|e| patch(recovery, self.variant, e, Patch::Placeholder))?;
+
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant<'de> {
+    type Error = DeserializeError;
+
+    #[inline]
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.tagged {
+            seed.deserialize(self.content())
+        } else {
+            descend(self.de.recovery.as_ref(), self.variant, seed)
+        }
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let (variant, recovery) = (self.variant, self.de.recovery.clone());
+        let result = de::Deserializer::deserialize_tuple(self.content(), len, visitor);
+
+        Self::locate(variant, recovery.as_ref(), result)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let (variant, tagged, recovery) = (self.variant, self.tagged, self.de.recovery.clone());
+        let result = de::Deserializer::deserialize_struct(self.content(), "", fields, visitor);
+
+        // errors of internally tagged content belong to the branch
+        if tagged {
+            result
+        } else {
+            Self::locate(variant, recovery.as_ref(), result)
+        }
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for Scalar<'de> {
+    type Error = DeserializeError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(Scalar {
+            value: self.value.clone(),
+            quoted: self.quoted,
+            options: self.options,
+            recovery: None,
+        })?;
+
+        Ok((variant, self))
+    }
+}
+
+/// Scalar only holds unit variant.
+impl<'de> de::VariantAccess<'de> for Scalar<'de> {
+    type Error = DeserializeError;
+
+    #[inline]
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn newtype_variant_seed<T>(self, _seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        Err(de::Error::invalid_type(
+            Unexpected::UnitVariant,
+            &"newtype variant",
+        ))
+    }
+
+    #[inline]
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(Unexpected::UnitVariant, &visitor))
+    }
+
+    #[inline]
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(Unexpected::UnitVariant, &visitor))
+    }
+}
+
+/// Whether `node` is replaced by [`Placeholder`] in the previous passes.
+#[inline]
+fn is_placeholder(recovery: Option<&Shared>, node: Node<'_>) -> bool {
+    recovery.is_some_and(|r| r.borrow().placeholders.contains(&node.id))
+}
+
+/// Deserialize `seed` from `child` node, or from [`Placeholder`] when the
+/// node is patched in the previous passes.
+///
+fn descend<'de, T>(
+    recovery: Option<&Shared>,
+    child: Node<'de>,
+    seed: T,
+) -> Result<T::Value, DeserializeError>
+where
+    T: de::DeserializeSeed<'de>,
+{
+    if is_placeholder(recovery, child) {
+        return seed.deserialize(Placeholder).map_err(|e| e.locate(child));
+    }
+
+    seed.deserialize(Deserializer {
+        node: child,
+        recovery: recovery.cloned(),
+        skip: None,
+    })
+    .map_err(|e| patch(recovery, child, e, Patch::Placeholder))
+}
+
+/// State shared between deserializers in a single [`Envs::deserialize`] call
+/// when [`Options::collect_errors`] is enabled.
+///
+/// Errors that happen before a visitor is consumed (value that can't be parsed,
+/// scalar requested from a branch) are recorded in `errors` and replaced by
+/// [`Placeholder`] value, so that deserialization keeps going.
+///
+/// Errors raised by visitors (missing field, unknown variant, ...) abort the pass.
+/// Those are recorded in `fatal` and the failing node is patched (replaced by
+/// placeholder, skipped or given a placeholder for the missing field) before
+/// deserialization starts over.
+///
+#[derive(Debug, Default)]
+struct Recovery {
+    errors: Vec<DeserializeError>,
+    fatal: Vec<DeserializeError>,
+    placeholders: HashSet<usize>,
+    skipped: HashSet<usize>,
+    missing: HashMap<usize, Vec<&'static str>>,
+    patched: bool,
+}
+
+type Shared = Rc<RefCell<Recovery>>;
+
+enum Patch {
+    Skip,
+    Placeholder,
+}
+
+impl Recovery {
+    /// Patch `node` so that the next pass doesn't fail with `e`,
+    /// `e` is expected to be located already.
+    ///
+    fn patch(&mut self, node: Node<'_>, e: &DeserializeError, patch: Patch) {
+        let patched = match (e, patch) {
+            (DeserializeError::MissingField { field, .. }, _) => {
+                let missing = self.missing.entry(node.id).or_default();
+
+                if missing.contains(field) {
+                    false
+                } else {
+                    missing.push(field);
+                    true
+                }
+            }
+            (_, Patch::Skip) => self.skipped.insert(node.id),
+            (_, Patch::Placeholder) => self.placeholders.insert(node.id),
+        };
+
+        // errors of a node that is already patched are caused by
+        // the placeholder, not by the envs.
+        if patched {
+            self.fatal.push(e.clone_located());
+            self.patched = true;
+        }
+    }
+}
+
+/// Locate `e` at `node` & patch the node when `e` is raised by its visitor
+/// (not located yet by a deeper node).
+///
+fn patch(
+    recovery: Option<&Shared>,
+    node: Node<'_>,
+    e: DeserializeError,
+    patch: Patch,
+) -> DeserializeError {
+    let raised = e.path().is_none();
+    let e = e.locate(node);
+
+    if let (true, Some(recovery)) = (raised, recovery) {
+        recovery.borrow_mut().patch(node, &e, patch);
+    }
+
+    e
+}
+
+/// Record `e` & continue with `f` when collecting errors, otherwise fails with `e`.
+///
+#[inline]
+fn recover<'de, V, F>(
+    recovery: Option<(&Shared, Node<'de>)>,
+    e: DeserializeError,
+    visitor: V,
+    f: F,
+) -> Result<V::Value, DeserializeError>
+where
+    V: Visitor<'de>,
+    F: FnOnce(Placeholder, V) -> Result<V::Value, DeserializeError>,
+{
+    match recovery {
+        Some((recovery, node)) => {
+            recovery.borrow_mut().errors.push(e.locate(node));
+            f(Placeholder, visitor)
+        }
+        None => Err(e),
+    }
+}
+
+/// Type that deserialize a single env value (or a key segment).
+///
+/// Since every env value is a string, any non string type
+/// are being parsed from its text.
+///
+/// Sequences are being read from inline array value, elements are separated
+/// by [`Options::array_sep`] and the whole value may be wrapped in brackets
+/// (`[a,b,c]`, as written by [`crate::ser::DefaultArrayFormatter`]).
+/// An element may be quoted to contain the separator :
+/// - `"a,b"` supports `\"`, `\\`, `\n`, `\r` & `\t` escapes
+/// - `'a,b'` is taken literally
+/// - unquoted element may escape the separator with `\,`
+///
+pub struct Scalar<'de> {
+    value: Cow<'de, str>,
+    quoted: bool,
+    options: ScalarOptions,
+    recovery: Option<(Shared, Node<'de>)>,
+}
+
+impl<'de> Scalar<'de> {
+    /// Scalar of a key segment (or variant name), its type is never inferred.
+    #[inline]
+    pub fn new(value: &'de str) -> Self {
+        Self {
+            value: Cow::Borrowed(value),
+            quoted: false,
+            options: ScalarOptions {
+                infer: Infer::none(),
+                ..ScalarOptions::default()
+            },
+            recovery: None,
+        }
+    }
+
+    #[inline]
+    fn recover<V, F>(
+        &self,
+        e: DeserializeError,
+        visitor: V,
+        f: F,
+    ) -> Result<V::Value, DeserializeError>
+    where
+        V: Visitor<'de>,
+        F: FnOnce(Placeholder, V) -> Result<V::Value, DeserializeError>,
+    {
+        recover(
+            self.recovery.as_ref().map(|(r, node)| (r, *node)),
+            e,
+            visitor,
+            f,
+        )
+    }
+}
+
+macro_rules! deserialize_parse {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            #[inline]
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                match self.value.trim().parse() {
+                    Ok(v) => visitor.$visit(v),
+                    Err(_) => {
+                        let e = de::Error::invalid_value(Unexpected::Str(&self.value), &visitor);
+                        self.recover(e, visitor, |p, v| p.$method(v))
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Scalar<'de> {
+    type Error = DeserializeError;
+
+    /// Infer type of the value according to [`Options::infer`].
+    #[inline]
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let infer = self.options.infer;
+        let value = self.value.trim();
+
+        if infer.null && value == "null" {
+            return visitor.visit_unit();
+        }
+
+        if infer.bool {
+            match value {
+                "true" => return visitor.visit_bool(true),
+                "false" => return visitor.visit_bool(false),
+                _ => (),
+            }
+        }
+
+        if infer.integer {
+            if let Ok(v) = value.parse::<u64>() {
+                return visitor.visit_u64(v);
+            }
+
+            if let Ok(v) = value.parse::<i64>() {
+                return visitor.visit_i64(v);
+            }
+        }
+
+        // only numeric text, `inf` & `NaN` are strings
+        let numeric = value.bytes().any(|b| b.is_ascii_digit())
+            && value
+                .bytes()
+                .all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b));
+
+        if infer.float && numeric {
+            if let Ok(v) = value.parse::<f64>() {
+                return visitor.visit_f64(v);
+            }
+        }
+
+        let array =
+            (value.starts_with('[') && value.ends_with(']')) || value.contains(self.options.sep);
+
+        if infer.array && array {
+            return self.deserialize_seq(visitor);
+        }
+
+        self.deserialize_str(visitor)
+    }
+
+    deserialize_parse! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    #[inline]
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            Cow::Borrowed(value) => visitor.visit_borrowed_str(value),
+            Cow::Owned(value) => visitor.visit_string(value),
+        }
+    }
+
+    #[inline]
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    #[inline]
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            Cow::Borrowed(value) => visitor.visit_borrowed_bytes(value.as_bytes()),
+            Cow::Owned(value) => visitor.visit_byte_buf(value.into_bytes()),
+        }
+    }
+
+    #[inline]
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    #[inline]
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        if !self.value.is_empty() || self.quoted {
+            return visitor.visit_some(self);
+        }
+
+        match self.options.empty {
+            Empty::None => visitor.visit_none(),
+            Empty::Some => visitor.visit_some(self),
+            Empty::Error => {
+                let e = de::Error::invalid_value(Unexpected::Str(""), &visitor);
+                self.recover(e, visitor, |p, v| p.deserialize_option(v))
+            }
+        }
+    }
+
+    #[inline]
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    #[inline]
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    #[inline]
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value {
+            Cow::Borrowed(value) => {
+                visitor.visit_seq(Elements::new(value, self.options, self.recovery))
+            }
+            // nested inline array is not supported
+            Cow::Owned(value) => Err(de::Error::invalid_type(Unexpected::Str(&value), &visitor)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    #[inline]
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    #[inline]
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let e = de::Error::invalid_type(Unexpected::Str(&self.value), &visitor);
+        self.recover(e, visitor, |p, v| p.deserialize_map(v))
+    }
+
+    #[inline]
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let e = de::Error::invalid_type(Unexpected::Str(&self.value), &visitor);
+        self.recover(e, visitor, |p, v| p.deserialize_struct(name, fields, v))
+    }
+
+    #[inline]
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.options.case.resolve(&self.value, variants) {
+            Ok(Some(variant)) => visitor.visit_enum(Scalar {
+                value: Cow::Borrowed(variant),
+                ..self
+            }),
+            Ok(None) => visitor.visit_enum(self),
+            Err(e) => self.recover(e, visitor, |p, v| p.deserialize_enum(name, variants, v)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+/// Sequence access over inline array value (`10,20,30` or `[10,20,30]`).
+///
+struct Elements<'de> {
+    rest: Option<&'de str>,
+    options: ScalarOptions,
+    recovery: Option<(Shared, Node<'de>)>,
+}
+
+impl<'de> Elements<'de> {
+    #[inline]
+    fn new(value: &'de str, options: ScalarOptions, recovery: Option<(Shared, Node<'de>)>) -> Self {
+        let mut value = value.trim();
+
+        if value.len() >= 2 && value.starts_with('[') && value.ends_with(']') {
+            value = value[1..value.len() - 1].trim();
+        }
+
+        // empty value are treated as empty array
+        let rest = if value.is_empty() { None } else { Some(value) };
+
+        // nested inline array is not supported
+        let options = ScalarOptions {
+            infer: options.infer.array(false),
+            ..options
+        };
+
+        Self {
+            rest,
+            options,
+            recovery,
+        }
+    }
+
+    /// Read the next element & whether it's quoted,
+    /// `rest` becomes `None` after the last element.
+    ///
+    fn element(&mut self) -> Result<Option<(Cow<'de, str>, bool)>, DeserializeError> {
+        let text = match self.rest.take() {
+            Some(text) => text.trim_start(),
+            None => return Ok(None),
+        };
+
+        let quote = match text.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => quote,
+            _ => {
+                let (element, rest) = Self::unquoted(text, self.options.sep);
+                self.rest = rest;
+                return Ok(Some((element, false)));
+            }
+        };
+
+        let (element, rest) = Self::quoted(&text[1..], quote).ok_or_else(|| {
+            <DeserializeError as de::Error>::custom(format_args!(
+                "unterminated quote in array element {}",
+                text
+            ))
+        })?;
+
+        let rest = rest.trim_start();
+
+        if rest.is_empty() {
+            return Ok(Some((element, true)));
+        }
+
+        match rest.strip_prefix(self.options.sep) {
+            Some(rest) => {
+                self.rest = Some(rest);
+                Ok(Some((element, true)))
+            }
+            None => Err(de::Error::custom(format_args!(
+                "expected `{}` after quoted array element, found {}",
+                self.options.sep, rest
+            ))),
+        }
+    }
+
+    /// Read element until the (unescaped) separator, returns the element &
+    /// the text after the separator.
+    ///
+    fn unquoted(text: &'de str, sep: char) -> (Cow<'de, str>, Option<&'de str>) {
+        let mut owned: Option<String> = None;
+        let mut chars = text.char_indices().peekable();
+
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '\\' => match chars.peek() {
+                    Some(&(_, next)) if next == sep || next == '\\' => {
+                        owned
+                            .get_or_insert_with(|| text[..idx].to_string())
+                            .push(next);
+                        chars.next();
+                    }
+                    _ => {
+                        if let Some(owned) = owned.as_mut() {
+                            owned.push(c);
+                        }
+                    }
+                },
+                c if c == sep => {
+                    let element = match owned {
+                        Some(owned) => Cow::Owned(owned.trim_end().to_string()),
+                        None => Cow::Borrowed(text[..idx].trim_end()),
+                    };
+
+                    return (element, Some(&text[idx + c.len_utf8()..]));
+                }
+                c => {
+                    if let Some(owned) = owned.as_mut() {
+                        owned.push(c);
+                    }
+                }
+            }
+        }
+
+        let element = match owned {
+            Some(owned) => Cow::Owned(owned.trim_end().to_string()),
+            None => Cow::Borrowed(text.trim_end()),
+        };
+
+        (element, None)
+    }
+
+    /// Read element until the closing `quote`, returns the element &
+    /// the text after the closing quote.
+    ///
+    fn quoted(text: &'de str, quote: char) -> Option<(Cow<'de, str>, &'de str)> {
+        let mut owned: Option<String> = None;
+        let mut chars = text.char_indices();
+
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                c if c == quote => {
+                    let element = match owned {
+                        Some(owned) => Cow::Owned(owned),
+                        None => Cow::Borrowed(&text[..idx]),
+                    };
+
+                    return Some((element, &text[idx + 1..]));
+                }
+                // single quoted element is taken literally
+                '\\' if quote == '"' => {
+                    let (_, next) = chars.next()?;
+                    let escaped = match next {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        c => c,
+                    };
+
+                    owned
+                        .get_or_insert_with(|| text[..idx].to_string())
+                        .push(escaped);
+                }
+                c => {
+                    if let Some(owned) = owned.as_mut() {
+                        owned.push(c);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for Elements<'de> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.element()? {
+            Some((element, quoted)) => seed
+                .deserialize(Scalar {
+                    value: element,
+                    quoted,
+                    options: self.options,
+                    recovery: self.recovery.clone(),
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Deserializer that produces a neutral value (`0`, `""`, `None`, empty sequence,
+/// struct of placeholders, first variant, ...) for whatever being requested.
+///
+/// Used in place of a value that failed when collecting errors.
+///
+struct Placeholder;
+
+macro_rules! deserialize_placeholder {
+    ($($method:ident => $visit:ident($value:expr),)*) => {
+        $(
+            #[inline]
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                visitor.$visit($value)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Placeholder {
+    type Error = DeserializeError;
+
+    #[inline]
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    deserialize_placeholder! {
+        deserialize_bool => visit_bool(false),
+        deserialize_i8 => visit_i8(0),
+        deserialize_i16 => visit_i16(0),
+        deserialize_i32 => visit_i32(0),
+        deserialize_i64 => visit_i64(0),
+        deserialize_u8 => visit_u8(0),
+        deserialize_u16 => visit_u16(0),
+        deserialize_u32 => visit_u32(0),
+        deserialize_u64 => visit_u64(0),
+        deserialize_f32 => visit_f32(0.0),
+        deserialize_f64 => visit_f64(0.0),
+        deserialize_char => visit_char('\0'),
+        deserialize_str => visit_borrowed_str(""),
+        deserialize_string => visit_borrowed_str(""),
+        deserialize_identifier => visit_borrowed_str(""),
+        deserialize_bytes => visit_borrowed_bytes(b""),
+        deserialize_byte_buf => visit_borrowed_bytes(b""),
+    }
+
+    #[inline]
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_none()
+    }
+
+    #[inline]
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    #[inline]
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    #[inline]
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Placeholders(0))
+    }
+
+    #[inline]
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Placeholders(len))
+    }
+
+    #[inline]
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Placeholders(len))
+    }
+
+    #[inline]
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(PlaceholderFields([].iter()))
+    }
+
+    #[inline]
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(PlaceholderFields(fields.iter()))
+    }
+
+    #[inline]
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match variants.first() {
+            Some(variant) => visitor.visit_enum(PlaceholderVariant(variant)),
+            None => visitor.visit_unit(),
+        }
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+/// Sequence of `n` [`Placeholder`].
+struct Placeholders(usize);
+
+impl<'de> de::SeqAccess<'de> for Placeholders {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.0 {
+            0 => Ok(None),
+            _ => {
+                self.0 -= 1;
+                seed.deserialize(Placeholder).map(Some)
+            }
+        }
+    }
+}
+
+/// Struct fields with [`Placeholder`] values.
+struct PlaceholderFields(std::slice::Iter<'static, &'static str>);
+
+impl<'de> de::MapAccess<'de> for PlaceholderFields {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        match self.0.next() {
+            Some(field) => seed.deserialize(Scalar::new(field)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(Placeholder)
+    }
+}
+
+/// Enum variant with [`Placeholder`] content.
+struct PlaceholderVariant(&'static str);
+
+impl<'de> de::EnumAccess<'de> for PlaceholderVariant {
+    type Error = DeserializeError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(Scalar::new(self.0))
+            .map(|variant| (variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for PlaceholderVariant {
+    type Error = DeserializeError;
+
+    #[inline]
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    #[inline]
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(Placeholder)
+    }
+
+    #[inline]
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Placeholders(len))
+    }
+
+    #[inline]
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(PlaceholderFields(fields.iter()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "UPPERCASE")]
+    struct Connection {
+        pool: u32,
+        timeout: u64,
+        retry: Vec<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "UPPERCASE")]
+    struct Database {
+        name: String,
+        username: String,
+        url: String,
+        connection: Connection,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "UPPERCASE")]
+    struct Config {
+        database: Database,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "UPPERCASE")]
+    struct Root {
+        config: Config,
+    }
+
+    #[test]
+    fn test_envs_tree() {
+        let envs = Envs::from_str("A__B__C=1\nA__B__D=2\nA__E=3\nF=4", Options::default()).unwrap();
+
+        let root = envs.root().children().collect::<Vec<_>>();
+        assert_eq!(root.len(), 2);
+        assert_eq!(root[0].name(), Some("A"));
+        assert_eq!(root[1].value(), Some("4"));
+
+        let a = root[0].children().collect::<Vec<_>>();
+        assert_eq!(a.len(), 2);
+        assert_eq!(a[0].children().len(), 2);
+        assert_eq!(a[1].value(), Some("3"));
+        assert_eq!(a[1].path(), vec!["A", "E"]);
+    }
+
+    #[test]
+    fn test_envs_walk() {
+        let envs = Envs::from_str("A__B__D=2\nF=4\nA__E=3\nA__B__C=1", Options::default()).unwrap();
+
+        let paths = envs
+            .walk()
+            .filter(|node| node.is_leaf())
+            .map(|node| (node.path().join("__"), node.value().unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            paths,
+            vec![
+                ("A__B__C".to_string(), "1"),
+                ("A__B__D".to_string(), "2"),
+                ("A__E".to_string(), "3"),
+                ("F".to_string(), "4")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_envs_order() {
+        let raw = "S__10=k\nS__2=c\nS__B=y\nS__0=a\nS__A=x\nS__1=b";
+        let leaves = |envs: &Envs| {
+            envs.walk()
+                .filter(|node| node.is_leaf())
+                .map(|node| node.path().join("__"))
+                .collect::<Vec<_>>()
+        };
+
+        let envs = Envs::from_str(raw, Options::default()).unwrap();
+
+        assert_eq!(
+            leaves(&envs),
+            vec!["S__0", "S__1", "S__2", "S__10", "S__A", "S__B"]
+        );
+
+        let envs = Envs::from_str(
+            "Z__B=1\nA=2\nZ__A=3\nZ__0=4",
+            Options::default().order(Order::Source),
+        )
+        .unwrap();
+
+        assert_eq!(leaves(&envs), vec!["Z__0", "Z__B", "Z__A", "A"]);
+    }
+
+    #[test]
+    fn test_from_str_nested_struct() {
+        let raw = r#"
+        # database section
+        CONFIG__DATABASE__NAME=name
+        CONFIG__DATABASE__USERNAME=username
+        CONFIG__DATABASE__URL=mysql://host:port
+        CONFIG__DATABASE__CONNECTION__POOL=10
+        CONFIG__DATABASE__CONNECTION__TIMEOUT=10
+        CONFIG__DATABASE__CONNECTION__RETRY=10,20,30
+        "#;
+
+        let root: Root = from_str(raw).unwrap();
+
+        assert_eq!(
+            root,
+            Root {
+                config: Config {
+                    database: Database {
+                        name: "name".to_string(),
+                        username: "username".to_string(),
+                        url: "mysql://host:port".to_string(),
+                        connection: Connection {
+                            pool: 10,
+                            timeout: 10,
+                            retry: vec![10, 20, 30],
+                        },
+                    },
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_str_inline_array() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Hosts {
+            names: Vec<String>,
+            ports: Vec<u16>,
+            empty: Vec<u16>,
+        }
+
+        let raw = r#"
+        NAMES=[ "a,b" , 'c\d', e\,f , "g\"h" ]
+        PORTS=80,443
+        EMPTY=[]
+        "#;
+
+        let hosts: Hosts = from_str(raw).unwrap();
+
+        assert_eq!(
+            hosts,
+            Hosts {
+                names: vec![
+                    "a,b".to_string(),
+                    "c\\d".to_string(),
+                    "e,f".to_string(),
+                    "g\"h".to_string(),
+                ],
+                ports: vec![80, 443],
+                empty: vec![],
+            }
+        );
+
+        let options = Options::default().array_sep(';');
+        let envs = Envs::from_str("NAMES=a,b;c\nPORTS=80\nEMPTY=", options).unwrap();
+        let hosts = envs.deserialize::<Hosts>().unwrap();
+
+        assert_eq!(hosts.names, vec!["a,b".to_string(), "c".to_string()]);
+
+        let result = from_str::<Hosts>("NAMES=\"a,b\nPORTS=\nEMPTY=");
+
+        assert!(result.is_err());
+
+        // comment char inside quoted element round-trips
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct Tags {
+            a: Vec<String>,
+        }
+
+        let tags = Tags {
+            a: vec!["x #y".to_string(), "z".to_string()],
+        };
+        let raw = crate::to_string(&tags).unwrap();
+
+        assert_eq!(raw, "a=[\"x #y\",\"z\"]\n");
+        assert_eq!(from_str::<Tags>(&raw).unwrap(), tags);
+        assert_eq!(
+            from_str::<Tags>("a=[it's, x] # comment").unwrap().a,
+            vec!["it's".to_string(), "x".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_from_str_indexed_array() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Server {
+            host: String,
+            port: u16,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Upstream {
+            servers: Vec<Server>,
+        }
+
+        let raw = r#"
+        SERVERS__1__HOST=b
+        SERVERS__1__PORT=81
+        SERVERS__0__HOST=a
+        SERVERS__0__PORT=80
+        "#;
+
+        let upstream: Upstream = from_str(raw).unwrap();
+
+        assert_eq!(
+            upstream.servers,
+            vec![
+                Server {
+                    host: "a".to_string(),
+                    port: 80,
+                },
+                Server {
+                    host: "b".to_string(),
+                    port: 81,
+                },
+            ]
+        );
+
+        let raw = "SERVERS__0__HOST=a\nSERVERS__0__PORT=80\nSERVERS__2__HOST=c";
+
+        match from_str::<Upstream>(raw) {
+            Err(DeserializeError::MissingIndex { path, index }) => {
+                assert_eq!(path.as_deref(), Some("SERVERS"));
+                assert_eq!(index, 1);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let raw = "SERVERS__0__HOST=a\nSERVERS__0__PORT=80\nSERVERS__HOST=c";
+
+        match from_str::<Upstream>(raw) {
+            Err(DeserializeError::MixedIndex { path, field }) => {
+                assert_eq!(path.as_deref(), Some("SERVERS"));
+                assert_eq!(field, "HOST");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_str_enum() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Env {
+            Development,
+            Production,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Credential {
+            #[serde(rename_all = "UPPERCASE")]
+            Password {
+                password: String,
+            },
+            Token(String),
+            None,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Application {
+            env: Env,
+            credential: Credential,
+            fallback: Credential,
+            anonymous: Credential,
+        }
+
+        let raw = r#"
+        ENV=production
+        CREDENTIAL__TYPE=password
+        CREDENTIAL__PASSWORD=some_password
+        FALLBACK__token=secret
+        ANONYMOUS=none
+        "#;
+
+        let application: Application = from_str(raw).unwrap();
+
+        assert_eq!(
+            application,
+            Application {
+                env: Env::Production,
+                credential: Credential::Password {
+                    password: "some_password".to_string(),
+                },
+                fallback: Credential::Token("secret".to_string()),
+                anonymous: Credential::None,
+            }
+        );
+
+        let raw = "ENV=staging\nCREDENTIAL=none\nFALLBACK=none\nANONYMOUS=none";
+
+        match from_str::<Application>(raw) {
+            Err(DeserializeError::UnknownVariant {
+                path,
+                line,
+                variant,
+                allowed,
+            }) => {
+                assert_eq!(path.as_deref(), Some("ENV"));
+                assert_eq!(line, Some(1));
+                assert_eq!(variant, "staging");
+                assert_eq!(allowed, &["development", "production"]);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let raw = "ENV=production\nCREDENTIAL__TYPE=oauth\nFALLBACK=none\nANONYMOUS=none";
+
+        match from_str::<Application>(raw) {
+            Err(e @ DeserializeError::UnknownVariant { .. }) => {
+                assert_eq!(e.path(), Some("CREDENTIAL__TYPE"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_str_case_insensitive() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        enum Env {
+            Development,
+            Production,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Logger {
+            level: String,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Application {
+            env: Env,
+            logger: Logger,
+        }
+
+        let raw = "APPLICATION__ENV=development\nAPPLICATION__LOGGER__LEVEL=info";
+        let envs = Envs::from_str(raw, Options::default().prefix("APPLICATION")).unwrap();
+
+        assert_eq!(
+            envs.deserialize::<Application>().unwrap(),
+            Application {
+                env: Env::Development,
+                logger: Logger {
+                    level: "info".to_string(),
+                },
+            }
+        );
+
+        let options = Options::default().prefix("APPLICATION").case(Case::Strict);
+        let envs = Envs::from_str(raw, options).unwrap();
+
+        assert!(envs.deserialize::<Application>().is_err());
+
+        #[derive(Debug, Deserialize)]
+        #[allow(non_snake_case)]
+        struct Ambiguous {
+            #[allow(dead_code)]
+            name: Option<String>,
+            #[allow(dead_code)]
+            NAME: Option<String>,
+        }
+
+        match from_str::<Ambiguous>("Name=x") {
+            Err(DeserializeError::Ambiguous {
+                path, candidates, ..
+            }) => {
+                assert_eq!(path.as_deref(), Some("Name"));
+                assert_eq!(candidates, vec!["name", "NAME"]);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_str_option() {
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Logger {
+            level: Option<String>,
+            file: Option<String>,
+            ports: Vec<Option<u16>>,
+        }
+
+        let raw = "LEVEL=\nPORTS=80,,443";
+        let logger: Logger = from_str(raw).unwrap();
+
+        assert_eq!(
+            logger,
+            Logger {
+                level: None,
+                file: None,
+                ports: vec![Some(80), None, Some(443)],
+            }
+        );
+
+        let envs = Envs::from_str("LEVEL=\nPORTS=", Options::default().empty(Empty::Some)).unwrap();
+        let logger = envs.deserialize::<Logger>().unwrap();
+
+        assert_eq!(logger.level.as_deref(), Some(""));
+        assert_eq!(logger.file, None);
+
+        // quoted empty value is always `Some("")`
+        let logger: Logger = from_str("LEVEL=\"\"\nPORTS=").unwrap();
+        assert_eq!(logger.level.as_deref(), Some(""));
+
+        let level = crate::to_string(&Logger {
+            level: Some(String::new()),
+            file: None,
+            ports: Vec::new(),
+        })
+        .unwrap();
+        assert_eq!(
+            from_str::<Logger>(&level).unwrap().level.as_deref(),
+            Some("")
+        );
+
+        let envs =
+            Envs::from_str("LEVEL=\nPORTS=", Options::default().empty(Empty::Error)).unwrap();
+
+        match envs.deserialize::<Logger>() {
+            Err(e @ DeserializeError::InvalidValue { .. }) => assert_eq!(e.path(), Some("LEVEL")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_str_any() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Null(()),
+            Bool(bool),
+            Integer(i64),
+            Float(f64),
+            List(Vec<Value>),
+            Text(String),
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Pool {
+            size: u32,
+            name: String,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Settings {
+            #[serde(flatten)]
+            pool: Pool,
+            values: Vec<Value>,
+            extra: Value,
+        }
+
+        let raw = r#"
+        SIZE=10
+        NAME=main
+        VALUES__0=null
+        VALUES__1=true
+        VALUES__2=-3
+        VALUES__3=1.5
+        VALUES__4=a,2
+        VALUES__5=inf
+        EXTRA=text
+        "#;
+
+        let settings: Settings = from_str(raw).unwrap();
+
+        assert_eq!(
+            settings,
+            Settings {
+                pool: Pool {
+                    size: 10,
+                    name: "main".to_string(),
+                },
+                values: vec![
+                    Value::Null(()),
+                    Value::Bool(true),
+                    Value::Integer(-3),
+                    Value::Float(1.5),
+                    Value::List(vec![Value::Text("a".to_string()), Value::Integer(2)]),
+                    Value::Text("inf".to_string()),
+                ],
+                extra: Value::Text("text".to_string()),
+            }
+        );
+
+        let envs =
+            Envs::from_str("A=true\nB=1,2", Options::default().infer(Infer::none())).unwrap();
+        let values = envs.deserialize::<HashMap<String, Value>>().unwrap();
+
+        assert_eq!(values["A"], Value::Text("true".to_string()));
+        assert_eq!(values["B"], Value::Text("1,2".to_string()));
+    }
+
+    #[test]
+    fn test_from_str_internally_tagged() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(tag = "TYPE", rename_all = "lowercase")]
+        enum Credential {
+            #[serde(rename_all = "UPPERCASE")]
+            Password { password: String },
+            #[serde(rename_all = "UPPERCASE")]
+            Token { token: String },
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Database {
+            credential: Credential,
+        }
+
+        let raw = "CREDENTIAL__TYPE=password\nCREDENTIAL__PASSWORD=some_password";
+        let database: Database = from_str(raw).unwrap();
+
+        assert_eq!(
+            database.credential,
+            Credential::Password {
+                password: "some_password".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_str_map() {
+        #[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+        enum Region {
+            Asia,
+            Europe,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Tenant {
+            quota: u32,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Config {
+            tenants: HashMap<String, Tenant>,
+            ports: BTreeMap<u16, String>,
+            regions: BTreeMap<Region, bool>,
+        }
+
+        let raw = r#"
+        CONFIG__TENANTS__ACME__QUOTA=5
+        CONFIG__TENANTS__Globex__QUOTA=7
+        CONFIG__PORTS__443=https
+        CONFIG__PORTS__80=http
+        CONFIG__REGIONS__ASIA=true
+        CONFIG__REGIONS__EUROPE=false
+        "#;
+
+        let envs = Envs::from_str(raw, Options::default().prefix("CONFIG")).unwrap();
+        let config = envs.deserialize::<Config>().unwrap();
+
+        assert_eq!(config.tenants.len(), 2);
+        assert_eq!(config.tenants["ACME"], Tenant { quota: 5 });
+        assert_eq!(config.tenants["Globex"], Tenant { quota: 7 });
+        assert_eq!(
+            config.ports.into_iter().collect::<Vec<_>>(),
+            vec![(80, "http".to_string()), (443, "https".to_string())]
+        );
+        assert_eq!(
+            config.regions.into_iter().collect::<Vec<_>>(),
+            vec![(Region::Asia, true), (Region::Europe, false)]
+        );
+
+        let options = Options::default().prefix("CONFIG").case(Case::Strict);
+        let envs = Envs::from_str("CONFIG__ACME=5\nCONFIG__Globex=7", options).unwrap();
+        let quotas = envs.deserialize::<HashMap<String, u32>>().unwrap();
+
+        assert_eq!(quotas["ACME"], 5);
+        assert_eq!(quotas["Globex"], 7);
+
+        match from_str::<BTreeMap<u16, String>>("80=http\nHTTPS=https") {
+            Err(e @ DeserializeError::InvalidValue { .. }) => assert_eq!(e.path(), Some("HTTPS")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_vars_prefixed() {
+        let vars = vec![
+            ("CONFIG__DATABASE__NAME", "name"),
+            ("CONFIG__DATABASE__POOL", "10"),
+            ("CONFIGURATION__DATABASE__POOL", "20"),
+            ("HOME", "/root"),
+        ];
+
+        let envs = Envs::from_vars(vars, Options::default().prefix("CONFIG")).unwrap();
+        let root = envs.root().children().collect::<Vec<_>>();
+        assert_eq!(root.len(), 1);
+        assert_eq!(root[0].name(), Some("DATABASE"));
+
+        let pool = root[0].children().nth(1).unwrap();
+        assert_eq!(pool.name(), Some("POOL"));
+        assert_eq!(pool.value(), Some("10"));
+    }
+
+    #[test]
+    fn test_from_env_prefixed() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Database {
+            pool: u32,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Config {
+            database: Database,
+        }
+
+        env::set_var("ENVIT_TEST_FROM_ENV__DATABASE__POOL", "10");
+
+        let config: Config = from_env_prefixed("ENVIT_TEST_FROM_ENV").unwrap();
+        assert_eq!(config.database.pool, 10);
+    }
+
+    #[test]
+    fn test_envs_conflict() {
+        let raw = "CONFIG__DATABASE=foo\nCONFIG__DATABASE__NAME=bar";
+
+        match Envs::from_str(raw, Options::default()) {
+            Err(DeserializeError::Conflict { leaf, branch }) => {
+                assert_eq!(leaf, "CONFIG__DATABASE");
+                assert_eq!(branch, "CONFIG__DATABASE__NAME");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let options = Options::default().prefix("CONFIG");
+        let envs = Envs::from_str(raw, options.clone().conflict(Conflict::PreferBranch)).unwrap();
+        let database = envs.root().children().next().unwrap();
+        assert_eq!(database.value(), None);
+        assert_eq!(database.children().len(), 1);
+
+        let envs = Envs::from_str(raw, options.clone().conflict(Conflict::PreferLeaf)).unwrap();
+        let database = envs.root().children().next().unwrap();
+        assert_eq!(database.value(), Some("foo"));
+        assert_eq!(database.children().len(), 0);
+
+        let envs = Envs::from_str(raw, options.conflict(Conflict::ValueEntry)).unwrap();
+        let database = envs.root().children().next().unwrap();
+        let children = database.children().collect::<Vec<_>>();
+        assert_eq!(database.value(), None);
+        assert_eq!(children[0].key(), "CONFIG__DATABASE___value");
+        assert_eq!(children[0].value(), Some("foo"));
+        assert_eq!(children[1].value(), Some("bar"));
+    }
+
+    #[test]
+    fn test_envs_duplicate() {
+        let raw = "A=1\nB=2\nA=3\nA=4";
+
+        let value = |envs: Envs| {
+            let a = envs.root().children().next().unwrap();
+            (a.value().unwrap().to_string(), a.line())
+        };
+
+        let envs = Envs::from_str(raw, Options::default()).unwrap();
+        assert_eq!(value(envs), ("4".to_string(), Some(4)));
+
+        let envs = Envs::from_str(raw, Options::default().duplicate(Duplicate::FirstWins)).unwrap();
+        assert_eq!(value(envs), ("1".to_string(), Some(1)));
+
+        match Envs::from_str(raw, Options::default().duplicate(Duplicate::Error)) {
+            Err(DeserializeError::Duplicate { key, lines }) => {
+                assert_eq!(key, "A");
+                assert_eq!(lines, vec![1, 3, 4]);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let raw = "A=1\nA=2\nB=1\nB=2";
+
+        match Envs::from_str(raw, Options::default().duplicate(Duplicate::Error)) {
+            Err(DeserializeError::Multiple(errors)) => {
+                let keys = errors
+                    .iter()
+                    .map(|e| (e.path().unwrap(), e.line().unwrap()))
+                    .collect::<Vec<_>>();
+                assert_eq!(keys, vec![("A", 1), ("B", 3)]);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_envs_malformed_line() {
+        let raw = "A=1\n\nnot a pair\nB=2";
+
+        match Envs::from_str(raw, Options::default()) {
+            Err(DeserializeError::Parse {
+                line, column, text, ..
+            }) => {
+                assert_eq!((line, column), (3, 11));
+                assert_eq!(text, "not a pair");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let envs = Envs::from_str(raw, Options::default().mode(Mode::Lenient)).unwrap();
+        assert_eq!(envs.root().children().len(), 2);
+        assert_eq!(envs.warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_envs_quoted_value() {
+        let raw = r#"
+        A="hello world" # comment
+        B='single $quoted \n' # comment
+        C=unquoted # comment
+        D=url#fragment
+        E="line\nbreak \"quoted\" \\ \$"
+        F=  spaced value
+        G=
+        H=""
+        "#;
+
+        let envs = Envs::from_str(raw, Options::default()).unwrap();
+        let values = envs
+            .root()
+            .children()
+            .map(|node| node.value().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            vec![
+                "hello world",
+                "single $quoted \\n",
+                "unquoted",
+                "url#fragment",
+                "line\nbreak \"quoted\" \\ \\$",
+                "spaced value",
+                "",
+                "",
+            ]
+        );
+
+        match Envs::from_str("A=1\nB=\"open\nC=3", Options::default()) {
+            Err(DeserializeError::Parse { line, column, .. }) => assert_eq!((line, column), (2, 3)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match Envs::from_str("A='quoted' text", Options::default()) {
+            Err(DeserializeError::Parse { line, column, .. }) => {
+                assert_eq!((line, column), (1, 12))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_envs_multiline_value() {
+        let raw = "A=1\r\nKEY=\"-----BEGIN KEY-----\r\nline 'one'\r\n-----END KEY-----\" # pem\r\nB='a\n\n b'\nC=3";
+
+        let envs = Envs::from_str(raw, Options::default()).unwrap();
+        let values = envs
+            .walk()
+            .filter(|node| node.is_leaf())
+            .map(|node| (node.name().unwrap(), node.value().unwrap(), node.line()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            vec![
+                ("A", "1", Some(1)),
+                ("B", "a\n\n b", Some(5)),
+                ("C", "3", Some(8)),
+                (
+                    "KEY",
+                    "-----BEGIN KEY-----\nline 'one'\n-----END KEY-----",
+                    Some(2)
+                ),
+            ]
+        );
+
+        match Envs::from_str("A=1\nB=\"multi\nline\" text\nC=3", Options::default()) {
+            Err(DeserializeError::Parse {
+                line, column, text, ..
+            }) => {
+                assert_eq!((line, column), (3, 7));
+                assert_eq!(text, "line\" text");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_envs_export() {
+        let raw = "export A=1\nexport\tB = 2\n  C   =3\nexport D\nexportE=4";
+
+        let envs = Envs::from_str(raw, Options::default()).unwrap();
+        let values = envs
+            .root()
+            .children()
+            .map(|node| (node.name().unwrap(), node.value().unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            vec![("A", "1"), ("B", "2"), ("C", "3"), ("exportE", "4")]
+        );
+
+        match Envs::from_str("A=1\nexport  MY KEY=2", Options::default()) {
+            Err(DeserializeError::Parse {
+                line,
+                column,
+                reason,
+                ..
+            }) => {
+                assert_eq!((line, column), (2, 11));
+                assert_eq!(reason, "whitespace in key");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_envs_interpolate() {
+        let raw = concat!(
+            "DB_HOST=localhost\n",
+            "CONFIG__NAME=app\n",
+            "CONFIG__URL=mysql://${DB_HOST}:${DB_PORT:-3306}/${CONFIG__NAME}\n",
+            "CONFIG__PRICE=$$5 ${MISSING}\n",
+            "CONFIG__RAW='${DB_HOST}'\n",
+        );
+
+        let options = Options::default()
+            .prefix("CONFIG")
+            .interpolate(Interpolate::Source);
+        let envs = Envs::from_str(raw, options.clone()).unwrap();
+        let values = envs
+            .root()
+            .children()
+            .map(|node| (node.name().unwrap(), node.value().unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            vec![
+                ("NAME", "app"),
+                ("PRICE", "$5 "),
+                ("RAW", "${DB_HOST}"),
+                ("URL", "mysql://localhost:3306/app"),
+            ]
+        );
+
+        // kept as is by default
+        let envs = Envs::from_str("A=${B}", Options::default()).unwrap();
+        assert_eq!(envs.root().children().next().unwrap().value(), Some("${B}"));
+
+        match Envs::from_str("A=1\nB=${C:?C is required}", options.clone()) {
+            Err(DeserializeError::Interpolation { key, line, reason }) => {
+                assert_eq!((key.as_str(), line), ("B", 2));
+                assert_eq!(reason, "`C` is not set: C is required");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // later pairs are never seen
+        let options = Options::default().interpolate(Interpolate::Source);
+        let envs = Envs::from_str("A=${B}\nB=x\nC=${A}${C}", options).unwrap();
+        let values = envs
+            .root()
+            .children()
+            .map(|node| node.value().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec!["", "x", ""]);
+    }
+
+    #[test]
+    fn test_envs_invalid_utf8() {
+        let raw: &[u8] = b"A=1\r\nB=\xff\r\nC=3";
+
+        match Envs::from_reader(raw, Options::default()) {
+            Err(DeserializeError::Parse { line, column, .. }) => assert_eq!((line, column), (2, 3)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let envs = Envs::from_reader(raw, Options::default().mode(Mode::Lenient)).unwrap();
+        let values = envs
+            .walk()
+            .filter_map(|node| node.value())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec!["1", "3"]);
+    }
+
+    #[test]
+    fn test_from_str_invalid_value() {
+        let raw = "CONFIG__DATABASE__NAME=name\nCONFIG__DATABASE__USERNAME=username\n\
+            CONFIG__DATABASE__URL=url\nCONFIG__DATABASE__CONNECTION__POOL=ten\n\
+            CONFIG__DATABASE__CONNECTION__TIMEOUT=10\nCONFIG__DATABASE__CONNECTION__RETRY=1";
+
+        match from_str::<Root>(raw) {
+            Err(e @ DeserializeError::InvalidValue { .. }) => {
+                assert_eq!(e.path(), Some("CONFIG__DATABASE__CONNECTION__POOL"));
+                assert_eq!(e.line(), Some(4));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_envs_collect_errors() {
+        let raw = "CONFIG__DATABASE__NAME=name\n\
+            CONFIG__DATABASE__CONNECTION__POOL=ten\n\
+            CONFIG__DATABASE__CONNECTION__TIMEOUT=-1\n\
+            CONFIG__DATABASE__CONNECTION__RETRY=1,x";
+
+        let options = Options::default().collect_errors(true);
+        let envs = Envs::from_str(raw, options).unwrap();
+
+        let errors = match envs.deserialize::<Root>() {
+            Err(DeserializeError::Multiple(errors)) => errors,
+            other => panic!("unexpected result: {:?}", other),
+        };
+
+        let errors = errors
+            .iter()
+            .map(|e| match e {
+                DeserializeError::MissingField { .. } => ("missing", e.path().unwrap()),
+                DeserializeError::InvalidValue { .. } => ("invalid", e.path().unwrap()),
+                _ => panic!("unexpected error: {:?}", e),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                ("invalid", "CONFIG__DATABASE__CONNECTION__POOL"),
+                ("invalid", "CONFIG__DATABASE__CONNECTION__RETRY"),
+                ("invalid", "CONFIG__DATABASE__CONNECTION__TIMEOUT"),
+                ("missing", "CONFIG__DATABASE__URL"),
+                ("missing", "CONFIG__DATABASE__USERNAME"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_str_missing_field() {
+        let raw = "CONFIG__DATABASE__NAME=name\nCONFIG__DATABASE__USERNAME=username";
+
+        match from_str::<Root>(raw) {
+            Err(e @ DeserializeError::MissingField { .. }) => {
+                assert_eq!(e.path(), Some("CONFIG__DATABASE__URL"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let envs = Envs::from_str(
+            "CONFIG__DATABASE__NAME=name",
+            Options::default().prefix("CONFIG"),
+        )
+        .unwrap();
+
+        match envs.deserialize::<Config>() {
+            Err(e) => assert_eq!(e.to_string(), "missing field CONFIG__DATABASE__USERNAME"),
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
 }
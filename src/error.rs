@@ -1,5 +1,9 @@
-use crate::serde::{
-    ser::{Error as SError},
+use crate::{
+    de::Node,
+    serde::{
+        de::{Error as DError, Expected, Unexpected},
+        ser::Error as SError,
+    },
 };
 use std::{error::Error as StdError, fmt, io};
 
@@ -11,6 +15,15 @@ pub enum SerializeError {
     /// dedicated for unknown state error when doing serializing
     /// in either both `crate::ser::MapFlow` or `crate::ser::SeqFlow`
     StateError,
+    /// dedicated for value that doesn't have any key (top level value
+    /// that is not a struct or a map).
+    MissingKey,
+    /// dedicated for branch (struct, map, sequence or variant with content)
+    /// inside an inline array.
+    NestedValue,
+    /// dedicated for map key that can't be written as a field, either
+    /// its type (`"f64"`) or the quoted key itself (`"\"a b\""`).
+    InvalidKey(String),
 }
 
 impl From<io::Error> for SerializeError {
@@ -25,7 +38,10 @@ impl fmt::Debug for SerializeError {
         match self {
             Self::CustomError(v) => write!(f, "custom error: {:?}", v),
             Self::IoError(e) => write!(f, "{:?}", e),
-            Self::StateError => write!(f, "{}", "StateError"),
+            Self::StateError => write!(f, "StateError"),
+            Self::MissingKey => write!(f, "MissingKey"),
+            Self::NestedValue => write!(f, "NestedValue"),
+            Self::InvalidKey(found) => write!(f, "InvalidKey({:?})", found),
         }
     }
 }
@@ -35,7 +51,15 @@ impl fmt::Display for SerializeError {
         match self {
             Self::CustomError(v) => write!(f, "custom error: {}", v),
             Self::IoError(e) => write!(f, "{}", e),
-            Self::StateError => write!(f, "{}", "StateError"),
+            Self::StateError => write!(f, "StateError"),
+            Self::MissingKey => write!(f, "value without key, expected a struct or a map"),
+            Self::NestedValue => write!(f, "branch inside an inline array"),
+            Self::InvalidKey(found) => write!(
+                f,
+                "map key must be a string, char, integer, bool or unit variant \
+                 without whitespace, `=`, field separator or shell metachar, found {}",
+                found
+            ),
         }
     }
 }
@@ -55,14 +79,365 @@ impl SError for SerializeError {
     }
 }
 
+/// Error when building `crate::de::Envs` or deserializing from it.
+///
+/// Errors that are raised while deserializing carry `path`, the full env key
+/// (`CONFIG__DATABASE__CONNECTION__POOL`) of the node that failed and `line`,
+/// the source line of its value (if any).
+///
 pub enum DeserializeError {
-    CustomError(String),
+    /// dedicated for custom error in user space
+    CustomError {
+        path: Option<String>,
+        line: Option<usize>,
+        msg: String,
+    },
+    /// dedicated for field that is required but doesn't exist,
+    /// `path` is the full env key of the field.
+    MissingField {
+        path: Option<String>,
+        field: &'static str,
+    },
+    /// dedicated for env value (or branch) that has different type than expected.
+    InvalidType {
+        path: Option<String>,
+        line: Option<usize>,
+        expected: String,
+        found: String,
+    },
+    /// dedicated for env value that has the right type but invalid value
+    /// (includes value that can't be parsed into expected type).
+    InvalidValue {
+        path: Option<String>,
+        line: Option<usize>,
+        expected: String,
+        found: String,
+    },
+    /// dedicated for enum variant that doesn't exist.
+    UnknownVariant {
+        path: Option<String>,
+        line: Option<usize>,
+        variant: String,
+        allowed: &'static [&'static str],
+    },
+    /// dedicated for field that doesn't exist (`#[serde(deny_unknown_fields)]`).
+    UnknownField {
+        path: Option<String>,
+        field: String,
+        allowed: &'static [&'static str],
+    },
+    /// dedicated for key segment (or variant value) that matches more than one
+    /// of the `candidates` fields (or variants) when ignoring case.
+    Ambiguous {
+        path: Option<String>,
+        line: Option<usize>,
+        name: String,
+        candidates: Vec<&'static str>,
+    },
+    /// dedicated for indexed array (`SERVERS__0__HOST`) that skips `index`.
+    MissingIndex { path: Option<String>, index: usize },
+    /// dedicated for indexed array (`SERVERS__0__HOST`) that also has
+    /// non index `field` (`SERVERS__HOST`).
+    MixedIndex { path: Option<String>, field: String },
+    /// dedicated for key that is both a value (`leaf`) & a branch,
+    /// `branch` is one of the keys under the branch.
+    Conflict { leaf: String, branch: String },
+    /// dedicated for key that is defined more than once
+    /// (see `crate::de::Duplicate::Error`).
+    Duplicate { key: String, lines: Vec<usize> },
+    /// dedicated for reference in the value of `key` that can't be expanded
+    /// (`${NAME:?message}` of unset `NAME` or malformed reference).
+    Interpolation {
+        key: String,
+        line: usize,
+        reason: String,
+    },
+    /// dedicated for line that is not a valid env pair,
+    /// `line` & `column` are 1-based.
+    Parse {
+        line: usize,
+        column: usize,
+        text: String,
+        reason: &'static str,
+    },
+    /// dedicated for std io::Error wrapper
+    IoError(io::Error),
+    /// dedicated for every error that were found in a single pass
+    /// (see `crate::de::Options::collect_errors` & `crate::de::Duplicate::Error`).
+    Multiple(Vec<DeserializeError>),
+}
+
+impl DeserializeError {
+    /// Full env key of the node that failed, if known.
+    #[inline]
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::CustomError { path, .. }
+            | Self::MissingField { path, .. }
+            | Self::InvalidType { path, .. }
+            | Self::InvalidValue { path, .. }
+            | Self::UnknownVariant { path, .. }
+            | Self::UnknownField { path, .. }
+            | Self::Ambiguous { path, .. }
+            | Self::MissingIndex { path, .. }
+            | Self::MixedIndex { path, .. } => path.as_deref(),
+            Self::Conflict { leaf, .. } => Some(leaf),
+            Self::Duplicate { key, .. } | Self::Interpolation { key, .. } => Some(key),
+            Self::Parse { .. } | Self::IoError(_) | Self::Multiple(_) => None,
+        }
+    }
+
+    /// Source line of the value that failed, if known.
+    #[inline]
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Self::CustomError { line, .. }
+            | Self::InvalidType { line, .. }
+            | Self::InvalidValue { line, .. }
+            | Self::UnknownVariant { line, .. }
+            | Self::Ambiguous { line, .. } => *line,
+            Self::Duplicate { lines, .. } => lines.first().copied(),
+            Self::Parse { line, .. } | Self::Interpolation { line, .. } => Some(*line),
+            _ => None,
+        }
+    }
+
+    /// Copy of a located error (that never be an [`DeserializeError::IoError`]).
+    pub(crate) fn clone_located(&self) -> Self {
+        match self {
+            Self::CustomError { path, line, msg } => Self::CustomError {
+                path: path.clone(),
+                line: *line,
+                msg: msg.clone(),
+            },
+            Self::MissingField { path, field } => Self::MissingField {
+                path: path.clone(),
+                field,
+            },
+            Self::InvalidType {
+                path,
+                line,
+                expected,
+                found,
+            } => Self::InvalidType {
+                path: path.clone(),
+                line: *line,
+                expected: expected.clone(),
+                found: found.clone(),
+            },
+            Self::InvalidValue {
+                path,
+                line,
+                expected,
+                found,
+            } => Self::InvalidValue {
+                path: path.clone(),
+                line: *line,
+                expected: expected.clone(),
+                found: found.clone(),
+            },
+            Self::UnknownVariant {
+                path,
+                line,
+                variant,
+                allowed,
+            } => Self::UnknownVariant {
+                path: path.clone(),
+                line: *line,
+                variant: variant.clone(),
+                allowed,
+            },
+            Self::UnknownField {
+                path,
+                field,
+                allowed,
+            } => Self::UnknownField {
+                path: path.clone(),
+                field: field.clone(),
+                allowed,
+            },
+            Self::Ambiguous {
+                path,
+                line,
+                name,
+                candidates,
+            } => Self::Ambiguous {
+                path: path.clone(),
+                line: *line,
+                name: name.clone(),
+                candidates: candidates.clone(),
+            },
+            Self::MissingIndex { path, index } => Self::MissingIndex {
+                path: path.clone(),
+                index: *index,
+            },
+            Self::MixedIndex { path, field } => Self::MixedIndex {
+                path: path.clone(),
+                field: field.clone(),
+            },
+            e => Self::CustomError {
+                path: e.path().map(ToString::to_string),
+                line: e.line(),
+                msg: e.to_string(),
+            },
+        }
+    }
+
+    /// Attach location of `node` to the error, unless
+    /// the error already has one (from a deeper node).
+    ///
+    pub(crate) fn locate(mut self, node: Node<'_>) -> Self {
+        let key = node.key();
+        let key = if key.is_empty() { None } else { Some(key) };
+
+        match &mut self {
+            Self::MissingField { path, field } if path.is_none() => {
+                *path = Some(node.join(field));
+            }
+            Self::UnknownField { path, .. }
+            | Self::MissingIndex { path, .. }
+            | Self::MixedIndex { path, .. }
+                if path.is_none() =>
+            {
+                *path = key
+            }
+            Self::CustomError { path, line, .. }
+            | Self::InvalidType { path, line, .. }
+            | Self::InvalidValue { path, line, .. }
+            | Self::UnknownVariant { path, line, .. }
+            | Self::Ambiguous { path, line, .. }
+                if path.is_none() =>
+            {
+                *path = key;
+                *line = node.line();
+            }
+            _ => (),
+        }
+
+        self
+    }
+}
+
+impl From<io::Error> for DeserializeError {
+    #[inline]
+    fn from(e: io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+/// Writes ` at PATH (line N)` suffix.
+fn write_location(
+    f: &mut fmt::Formatter<'_>,
+    path: &Option<String>,
+    line: &Option<usize>,
+) -> fmt::Result {
+    if let Some(path) = path {
+        write!(f, " at {}", path)?;
+    }
+
+    match line {
+        Some(line) => write!(f, " (line {})", line),
+        None => Ok(()),
+    }
+}
+
+fn join_allowed(allowed: &[&str]) -> String {
+    allowed
+        .iter()
+        .map(|v| format!("`{}`", v))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl fmt::Debug for DeserializeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::CustomError(v) => write!(f, "custom error: {:?}", v),
+            Self::CustomError { path, line, msg } => write!(
+                f,
+                "custom error: path {:?}, line {:?}, msg {:?}",
+                path, line, msg
+            ),
+            Self::MissingField { path, field } => {
+                write!(f, "missing field: path {:?}, field {:?}", path, field)
+            }
+            Self::InvalidType {
+                path,
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "invalid type: path {:?}, line {:?}, expected {:?}, found {:?}",
+                path, line, expected, found
+            ),
+            Self::InvalidValue {
+                path,
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "invalid value: path {:?}, line {:?}, expected {:?}, found {:?}",
+                path, line, expected, found
+            ),
+            Self::UnknownVariant {
+                path,
+                line,
+                variant,
+                allowed,
+            } => write!(
+                f,
+                "unknown variant: path {:?}, line {:?}, variant {:?}, allowed {:?}",
+                path, line, variant, allowed
+            ),
+            Self::UnknownField {
+                path,
+                field,
+                allowed,
+            } => write!(
+                f,
+                "unknown field: path {:?}, field {:?}, allowed {:?}",
+                path, field, allowed
+            ),
+            Self::Ambiguous {
+                path,
+                line,
+                name,
+                candidates,
+            } => write!(
+                f,
+                "ambiguous: path {:?}, line {:?}, name {:?}, candidates {:?}",
+                path, line, name, candidates
+            ),
+            Self::MissingIndex { path, index } => {
+                write!(f, "missing index: path {:?}, index {:?}", path, index)
+            }
+            Self::MixedIndex { path, field } => {
+                write!(f, "mixed index: path {:?}, field {:?}", path, field)
+            }
+            Self::Conflict { leaf, branch } => {
+                write!(f, "conflict: leaf {:?}, branch {:?}", leaf, branch)
+            }
+            Self::Duplicate { key, lines } => {
+                write!(f, "duplicate: key {:?}, lines {:?}", key, lines)
+            }
+            Self::Interpolation { key, line, reason } => write!(
+                f,
+                "interpolation: key {:?}, line {}, reason {:?}",
+                key, line, reason
+            ),
+            Self::Parse {
+                line,
+                column,
+                text,
+                reason,
+            } => write!(
+                f,
+                "parse error: line {}, column {}, text {:?}, reason {:?}",
+                line, column, text, reason
+            ),
+            Self::IoError(e) => write!(f, "{:?}", e),
+            Self::Multiple(errors) => f.debug_list().entries(errors).finish(),
         }
     }
 }
@@ -70,22 +445,177 @@ impl fmt::Debug for DeserializeError {
 impl fmt::Display for DeserializeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::CustomError(v) => write!(f, "custom error: {}", v),
+            Self::CustomError { path, line, msg } => {
+                write!(f, "{}", msg)?;
+                write_location(f, path, line)
+            }
+            Self::MissingField { path, field } => {
+                write!(f, "missing field {}", path.as_deref().unwrap_or(field))
+            }
+            Self::InvalidType {
+                path,
+                line,
+                expected,
+                found,
+            } => {
+                write!(f, "invalid type: {}, expected {}", found, expected)?;
+                write_location(f, path, line)
+            }
+            Self::InvalidValue {
+                path,
+                line,
+                expected,
+                found,
+            } => {
+                write!(f, "invalid value: {}, expected {}", found, expected)?;
+                write_location(f, path, line)
+            }
+            Self::UnknownVariant {
+                path,
+                line,
+                variant,
+                allowed,
+            } => {
+                write!(
+                    f,
+                    "unknown variant `{}`, expected one of {}",
+                    variant,
+                    join_allowed(allowed)
+                )?;
+                write_location(f, path, line)
+            }
+            Self::UnknownField {
+                path,
+                field,
+                allowed,
+            } => {
+                write!(
+                    f,
+                    "unknown field `{}`, expected one of {}",
+                    field,
+                    join_allowed(allowed)
+                )?;
+                write_location(f, path, &None)
+            }
+            Self::Ambiguous {
+                path,
+                line,
+                name,
+                candidates,
+            } => {
+                write!(
+                    f,
+                    "`{}` matches more than one of {} when ignoring case",
+                    name,
+                    join_allowed(candidates)
+                )?;
+                write_location(f, path, line)
+            }
+            Self::MissingIndex { path, index } => {
+                write!(f, "missing index {} of indexed array", index)?;
+                write_location(f, path, &None)
+            }
+            Self::MixedIndex { path, field } => {
+                write!(
+                    f,
+                    "field `{}` is mixed with indices of indexed array",
+                    field
+                )?;
+                write_location(f, path, &None)
+            }
+            Self::Conflict { leaf, branch } => {
+                write!(f, "{} is both a value and a branch (of {})", leaf, branch)
+            }
+            Self::Duplicate { key, lines } => write!(
+                f,
+                "{} is defined more than once (lines {})",
+                key,
+                lines
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Interpolation { key, line, reason } => {
+                write!(f, "{} at {} (line {})", reason, key, line)
+            }
+            Self::Parse {
+                line,
+                column,
+                text,
+                reason,
+            } => write!(f, "{} at line {} column {}: {}", reason, line, column, text),
+            Self::IoError(e) => write!(f, "{}", e),
+            Self::Multiple(errors) => {
+                write!(f, "{} errors:", errors.len())?;
+
+                for e in errors {
+                    write!(f, "\n  - {}", e)?;
+                }
+
+                Ok(())
+            }
         }
     }
 }
 
 impl StdError for DeserializeError {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
-        None
+        match self {
+            Self::IoError(e) => Some(e),
+            _ => None,
+        }
     }
 }
 
-impl SError for DeserializeError {
+impl DError for DeserializeError {
     fn custom<T>(msg: T) -> Self
     where
         T: fmt::Display,
     {
-        Self::CustomError(format!("custom error: {}", msg))
+        Self::CustomError {
+            path: None,
+            line: None,
+            msg: msg.to_string(),
+        }
+    }
+
+    fn invalid_type(unexp: Unexpected<'_>, exp: &dyn Expected) -> Self {
+        Self::InvalidType {
+            path: None,
+            line: None,
+            expected: exp.to_string(),
+            found: unexp.to_string(),
+        }
+    }
+
+    fn invalid_value(unexp: Unexpected<'_>, exp: &dyn Expected) -> Self {
+        Self::InvalidValue {
+            path: None,
+            line: None,
+            expected: exp.to_string(),
+            found: unexp.to_string(),
+        }
+    }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        Self::UnknownVariant {
+            path: None,
+            line: None,
+            variant: variant.to_string(),
+            allowed: expected,
+        }
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        Self::UnknownField {
+            path: None,
+            field: field.to_string(),
+            allowed: expected,
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self::MissingField { path: None, field }
     }
 }
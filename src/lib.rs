@@ -5,6 +5,10 @@ pub mod error;
 pub mod ser;
 pub mod types;
 
+pub use de::{from_env, from_env_prefixed, from_reader, from_str, Deserializer, Envs};
+pub use error::{DeserializeError, SerializeError};
+pub use ser::{to_string, to_vec, to_writer, Serializer};
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -1,37 +1,170 @@
-///!
-///! [`StringFormatter`] & [`ArrayFormatter`] & [`FieldFormatter`] are
-///! static trait, means you don't need to have a real struct implementation
-///! since most of the methods doesn't need to have instance of the struct.
-///!
-///! When serializing a data structure into envs, you need to realize that :
-///! - env value could be another field, thus we need to know whether
-///!   current node is leaf/value node
-///! - any `Serializer::serialize_*` (value serialization fn) can be called in any cases,
-///!   not only when serializing a value. It could be called when serializing key in env pair.
-///! - Serializing sibling node requires us to keep track the parent nodes (allocations).
-///!
-///! Since in `serde`, all serialization also derive from Serializer function including
-///! field/key serializer or value serializer inside variant. So, we need to create
-///! flag mechanism to check whether current context is come from `MapFlow` or `SeqFlow`.
-///!
+//!
+//! [`StringFormatter`] & [`ArrayFormatter`] & [`FieldFormatter`] are
+//! static trait, means you don't need to have a real struct implementation
+//! since most of the methods doesn't need to have instance of the struct.
+//!
+//! When serializing a data structure into envs, you need to realize that :
+//! - env value could be another field, thus we need to know whether
+//!   current node is leaf/value node
+//! - any `Serializer::serialize_*` (value serialization fn) can be called in any cases,
+//!   not only when serializing a value. It could be called when serializing key in env pair.
+//! - Serializing sibling node requires us to keep track the parent nodes (allocations).
+//!
+//! So, the serializer keeps the fields of the current node (from the root) as a stack,
+//! struct fields, map keys & enum variants are pushed before serializing their
+//! value and popped after it. Every leaf value replays the whole stack as its key,
+//! thus each leaf becomes a single env pair (`DATABASE__NAME="name"`).
+//!
+//! Elements of a sequence are either written inline (`RETRY=[10,20,30]`), where
+//! values are written without key (see `Serializer::is_value`), or each element
+//! is written under its index (`SERVERS__0__HOST=..`), see [`Sequence`].
+//!
 use crate::{
     error::SerializeError,
     serde::{ser, Serialize},
     types::{ArrayFormatter, FieldFormatter, StringFormatter},
 };
 
-use std::{io, marker::PhantomData};
+use std::{borrow::Cow, io, marker::PhantomData};
 
+/// Type that implement `StringFormatter` for dotenv double quoted value,
+/// `"`, `\\`, newline, carriage return & tab are escaped the same way
+/// as `crate::de::Envs` reads them.
+///
+/// The value is not shell safe (`$` & backtick are kept as is), use
+/// [`PosixStringFormatter`], [`BashStringFormatter`] or [`FishStringFormatter`]
+/// for env file that is sourced by shell.
+///
 pub struct DefaultStringFormatter;
 
 impl StringFormatter for DefaultStringFormatter {
+    fn format<W>(f: &mut W, v: &str) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        write!(f, "\"")?;
+
+        for c in v.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+
+        write!(f, "\"")
+    }
+}
+
+/// Shell can't hold NUL char in any of its string.
+#[inline]
+fn ensure_no_nul(v: &str) -> io::Result<()> {
+    if v.contains('\0') {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "NUL char can't be written in shell string",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Type that implement `StringFormatter` for POSIX shell (`sh`, `dash`, `bash`, ...).
+///
+/// Value is single quoted, where every char is literal, and `'` is written
+/// as `'\''` (close quote, escaped quote, open quote). Fails on NUL char.
+///
+pub struct PosixStringFormatter;
+
+impl StringFormatter for PosixStringFormatter {
+    fn format<W>(f: &mut W, v: &str) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        ensure_no_nul(v)?;
+        write!(f, "'{}'", v.replace('\'', "'\\''"))
+    }
+}
+
+/// Type that implement `StringFormatter` for bash ANSI-C quoting (`$'...'`).
+///
+/// `'` & `\\` are escaped, newline, carriage return & tab are written as
+/// `\\n`, `\\r` & `\\t` and the other control chars as `\\xHH`, thus the value
+/// always stays in a single line. Fails on NUL char.
+///
+pub struct BashStringFormatter;
+
+impl StringFormatter for BashStringFormatter {
+    fn format<W>(f: &mut W, v: &str) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        ensure_no_nul(v)?;
+        write!(f, "$'")?;
+
+        for c in v.chars() {
+            match c {
+                '\'' => write!(f, "\\'")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                c if c.is_ascii_control() => write!(f, "\\x{:02x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+
+        write!(f, "'")
+    }
+}
+
+/// Type that implement `StringFormatter` for fish shell.
+///
+/// Value is single quoted, where only `'` & `\\` need to be escaped.
+/// Fails on NUL char.
+///
+pub struct FishStringFormatter;
+
+impl StringFormatter for FishStringFormatter {
+    fn format<W>(f: &mut W, v: &str) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        ensure_no_nul(v)?;
+        write!(f, "'{}'", v.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+}
+
+/// Type that implement `StringFormatter` that writes value as is (unquoted).
+///
+/// Only ASCII alphanumeric & `_-./:@%+=` chars are allowed, so that the value
+/// means the same for shell & dotenv (including inline array element).
+/// Fails on the other chars.
+///
+pub struct RawStringFormatter;
+
+impl RawStringFormatter {
     #[inline]
+    fn is_safe(c: char) -> bool {
+        c.is_ascii_alphanumeric() || "_-./:@%+=".contains(c)
+    }
+}
+
+impl StringFormatter for RawStringFormatter {
     fn format<W>(f: &mut W, v: &str) -> io::Result<()>
     where
         W: io::Write + ?Sized,
     {
-        // // TODO: do we need to ensure string quotation validity ?
-        write!(f, "\"{}\"", v)
+        match v.chars().find(|c| !Self::is_safe(*c)) {
+            Some(c) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsafe char {:?} in unquoted value {:?}", c, v),
+            )),
+            None => write!(f, "{}", v),
+        }
     }
 }
 
@@ -50,7 +183,7 @@ impl ArrayFormatter for DefaultArrayFormatter {
     where
         W: io::Write + ?Sized,
     {
-        write!(f, "{}", "[")
+        write!(f, "[")
     }
 
     #[inline]
@@ -58,14 +191,51 @@ impl ArrayFormatter for DefaultArrayFormatter {
     where
         W: io::Write + ?Sized,
     {
-        write!(f, "{}", ',')
+        write!(f, ",")
     }
 
     fn end<W>(f: &mut W) -> io::Result<()>
     where
         W: io::Write + ?Sized,
     {
-        write!(f, "{}", "]")
+        write!(f, "]")
+    }
+}
+
+/// Type that implement `ArrayFormatter` for bare list (`10,20,30`).
+///
+/// it uses :
+/// - nothing for `ArrayFormatter::begin` & `ArrayFormatter::end`
+/// - ',' for `ArrayFormatter::separate`
+///
+/// List with any string (`1,"a #b"`) is written through the `StringFormatter` as a
+/// whole, since quotes of its elements aren't read back otherwise.
+///
+pub struct BareArrayFormatter;
+
+impl ArrayFormatter for BareArrayFormatter {
+    #[inline]
+    fn begin<W>(_f: &mut W) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        Ok(())
+    }
+
+    #[inline]
+    fn separate<W>(f: &mut W) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        write!(f, ",")
+    }
+
+    #[inline]
+    fn end<W>(_f: &mut W) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        Ok(())
     }
 }
 
@@ -77,7 +247,7 @@ impl FieldFormatter for DefaultFieldFormatter {
     where
         W: io::Write + ?Sized,
     {
-        write!(f, "{}", "=")
+        write!(f, "=")
     }
 
     #[inline]
@@ -85,7 +255,7 @@ impl FieldFormatter for DefaultFieldFormatter {
     where
         W: io::Write + ?Sized,
     {
-        write!(f, "{}", "__")
+        write!(f, "__")
     }
 
     #[inline]
@@ -93,7 +263,62 @@ impl FieldFormatter for DefaultFieldFormatter {
     where
         W: io::Write + ?Sized,
     {
-        write!(f, "{}", "\n")
+        writeln!(f)
+    }
+}
+
+/// Case of fields (struct fields & variants) in serialized key, prefix
+/// & map keys are always written as is since they're user data.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCase {
+    /// `DATABASE__NAME`
+    Upper,
+    /// `database__name`
+    Lower,
+    /// Fields are written as they are named.
+    Preserve,
+}
+
+impl Default for KeyCase {
+    #[inline]
+    fn default() -> Self {
+        Self::Preserve
+    }
+}
+
+impl KeyCase {
+    #[inline]
+    fn apply<'a>(self, field: Cow<'a, str>) -> Cow<'a, str> {
+        match self {
+            Self::Upper if field.bytes().any(|b| b.is_ascii_lowercase()) => {
+                Cow::Owned(field.to_ascii_uppercase())
+            }
+            Self::Lower if field.bytes().any(|b| b.is_ascii_uppercase()) => {
+                Cow::Owned(field.to_ascii_lowercase())
+            }
+            _ => field,
+        }
+    }
+}
+
+/// How sequences are being written.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sequence {
+    /// Elements are written in a single value (`RETRY=[10,20,30]`) using
+    /// the array formatter, falls back to [`Sequence::Indexed`] when
+    /// an element is not a scalar (struct, map, sequence, ...).
+    Inline,
+    /// Each element is written under its index
+    /// (`RETRY__0=10`, `SERVERS__0__HOST=..`), empty sequence is written inline.
+    Indexed,
+}
+
+impl Default for Sequence {
+    #[inline]
+    fn default() -> Self {
+        Self::Inline
     }
 }
 
@@ -101,8 +326,8 @@ impl FieldFormatter for DefaultFieldFormatter {
 ///
 /// It implements `ser::Serializer`.
 ///
-/// Any calls to serialize_* after calling non_value() and set_value()
-/// will be not print a pair_sep.
+/// Any calls to serialize_* after set_value()
+/// (elements of inline array) only write the value, without its key.
 ///
 pub struct Serializer<'a, A, W, F, S>
 where
@@ -111,22 +336,34 @@ where
     F: FieldFormatter + Sized,
     S: StringFormatter + Sized,
 {
-    // INFO: we need to use custom Writer in here since
-    //       we need to be able to replay parents fields serialization
+    // INFO: we need to be able to replay parents fields serialization
     //       for serialize the next sibling of current node.
     //       Thus, checking current node is leaf/value or not, is important.
     //
     output: W,
     flag_value: bool,
-    // TODO: I need to store fields traversal history (as a Stack) in here
-    //       so that I can print (duplicately) parent node
-    stack: Vec<&'a str>,
+    // fields traversal history of current node (starting from prefix),
+    // map keys are owned since they are serialized on the fly.
+    stack: Vec<Cow<'a, str>>,
+    case: KeyCase,
+    sequence: Sequence,
     _array: PhantomData<A>,
     _field: PhantomData<F>,
     _string: PhantomData<S>,
 }
 
-
+impl<'a, W> Serializer<'a, DefaultArrayFormatter, W, DefaultFieldFormatter, DefaultStringFormatter>
+where
+    W: io::Write + Sized,
+{
+    /// Create serializer that writes into `output` with default formatters,
+    /// use [`Builder`] to select other formatters.
+    ///
+    #[inline]
+    pub fn new(output: W) -> Self {
+        Builder::new().build(output)
+    }
+}
 
 impl<'a, A, W, F, S> Serializer<'a, A, W, F, S>
 where
@@ -135,77 +372,293 @@ where
     F: FieldFormatter + Sized,
     S: StringFormatter + Sized,
 {
+    /// Unwrap the underlying writer.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
     #[inline]
     pub(crate) fn set_value(&mut self) {
         self.flag_value = true;
     }
 
+    /// Guards to check whether current node is value or not.
+    ///
+    /// This useful when traversing using SeqFlow, so that in case
+    /// Serialize call method that render Serializer::serialize_*
+    /// that directly render value, we could check whether the value
+    /// is an element of inline array or a leaf. If it's an element
+    /// then only render the value, otherwise render the whole pair.
+    ///
     #[inline]
-    pub(crate) fn non_value(&mut self) {
-        self.flag_value = false;
+    pub(crate) fn is_value(&self) -> bool {
+        self.flag_value
+    }
+
+    /// Create serializer for an element of inline array.
+    pub(crate) fn inline(&self) -> Serializer<'a, A, Vec<u8>, F, S> {
+        let mut ser = Serializer {
+            output: Vec::new(),
+            flag_value: false,
+            stack: Vec::new(),
+            case: self.case,
+            sequence: self.sequence,
+            _array: PhantomData,
+            _field: PhantomData,
+            _string: PhantomData,
+        };
+
+        ser.set_value();
+        ser
     }
 
+    /// Push struct field or variant `field` of the next node, following [`KeyCase`].
     #[inline]
-    pub(crate) fn render_pair_sep(&mut self) {
-        if self.is_value() {
-            F::pair_sep(&mut self.output);
-            self.non_value();
+    pub(crate) fn push_field(&mut self, field: &'static str) {
+        let field = self.case.apply(Cow::Borrowed(field));
+        self.stack.push(field);
+    }
+
+    /// Render key of current node, by replaying every fields in the stack.
+    pub(crate) fn render_key(&mut self) -> Result<(), SerializeError> {
+        if self.stack.is_empty() {
+            return Err(SerializeError::MissingKey);
+        }
+
+        for (idx, field) in self.stack.iter().enumerate() {
+            if idx > 0 {
+                F::field_sep(&mut self.output)?;
+            }
+
+            self.output.write_all(field.as_bytes())?;
         }
+
+        Ok(())
     }
 
-    /// Guards to check whether current node is value or not.
+    /// Render leaf `value`, as a whole env pair unless
+    /// it's an element of inline array.
     ///
-    /// This useful when traversing using SeqFlow or MapFlow,
-    /// so that in case Serialize call method that render
-    /// Serializer::serialize_* that directly render value,
-    /// we could check whether the value is a leaf or a branch.
-    /// If it's a branch then don't render pair_sep, otherwise
-    /// render the pair_sep.
+    pub(crate) fn render_leaf<V>(&mut self, value: V) -> Result<(), SerializeError>
+    where
+        V: FnOnce(&mut W) -> io::Result<()>,
+    {
+        if self.is_value() {
+            return value(&mut self.output).map_err(SerializeError::from);
+        }
+
+        self.render_key()?;
+        F::pair_sep(&mut self.output)?;
+        value(&mut self.output)?;
+        F::value_sep(&mut self.output).map_err(SerializeError::from)
+    }
+
+    /// Guards branch (struct, map, sequence, variant with content)
+    /// to not being rendered as an element of inline array.
     ///
     #[inline]
-    pub(crate) fn is_value(&self) -> bool {
-        self.flag_value
+    pub(crate) fn ensure_branch(&self) -> Result<(), SerializeError> {
+        if self.is_value() {
+            Err(SerializeError::NestedValue)
+        } else {
+            Ok(())
+        }
     }
 }
 
+/// Builder of [`Serializer`] that selects its formatter types & options.
 ///
-/// Derived State to be used in figuring out state inside
-/// the loop of Serialize* when iterating over its element.
+/// ```
+/// use envit::ser::{Builder, DefaultArrayFormatter, KeyCase};
 ///
-#[derive(Clone, Copy)]
-pub(crate) enum State {
-    Initial,
-    Next,
+/// let mut ser = Builder::new()
+///     .array_formatter::<DefaultArrayFormatter>()
+///     .prefix("CONFIG")
+///     .key_case(KeyCase::Upper)
+///     .build(Vec::new());
+/// ```
+///
+pub struct Builder<A, F, S>
+where
+    A: ArrayFormatter + Sized,
+    F: FieldFormatter + Sized,
+    S: StringFormatter + Sized,
+{
+    prefix: Option<String>,
+    case: KeyCase,
+    sequence: Sequence,
+    _array: PhantomData<A>,
+    _field: PhantomData<F>,
+    _string: PhantomData<S>,
+}
+
+impl Builder<DefaultArrayFormatter, DefaultFieldFormatter, DefaultStringFormatter> {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            prefix: None,
+            case: KeyCase::default(),
+            sequence: Sequence::default(),
+            _array: PhantomData,
+            _field: PhantomData,
+            _string: PhantomData,
+        }
+    }
+}
+
+impl Default for Builder<DefaultArrayFormatter, DefaultFieldFormatter, DefaultStringFormatter> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A, F, S> Builder<A, F, S>
+where
+    A: ArrayFormatter + Sized,
+    F: FieldFormatter + Sized,
+    S: StringFormatter + Sized,
+{
+    /// Formatter of inline array (default [`DefaultArrayFormatter`]).
+    #[inline]
+    pub fn array_formatter<T>(self) -> Builder<T, F, S>
+    where
+        T: ArrayFormatter + Sized,
+    {
+        Builder {
+            prefix: self.prefix,
+            case: self.case,
+            sequence: self.sequence,
+            _array: PhantomData,
+            _field: PhantomData,
+            _string: PhantomData,
+        }
+    }
+
+    /// Formatter of separators in pair (default [`DefaultFieldFormatter`]).
+    #[inline]
+    pub fn field_formatter<T>(self) -> Builder<A, T, S>
+    where
+        T: FieldFormatter + Sized,
+    {
+        Builder {
+            prefix: self.prefix,
+            case: self.case,
+            sequence: self.sequence,
+            _array: PhantomData,
+            _field: PhantomData,
+            _string: PhantomData,
+        }
+    }
+
+    /// Formatter of string value (default [`DefaultStringFormatter`]).
+    #[inline]
+    pub fn string_formatter<T>(self) -> Builder<A, F, T>
+    where
+        T: StringFormatter + Sized,
+    {
+        Builder {
+            prefix: self.prefix,
+            case: self.case,
+            sequence: self.sequence,
+            _array: PhantomData,
+            _field: PhantomData,
+            _string: PhantomData,
+        }
+    }
+
+    /// Write every key under `prefix` (the same as the deserializer's
+    /// `crate::de::Options::prefix`), `prefix` may contain several fields (`APP__CONFIG`).
+    ///
+    #[inline]
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Case of fields in key (default [`KeyCase::Preserve`]).
+    #[inline]
+    pub fn key_case(mut self, case: KeyCase) -> Self {
+        self.case = case;
+        self
+    }
+
+    /// How sequences are being written (default [`Sequence::Inline`]).
+    #[inline]
+    pub fn sequence(mut self, sequence: Sequence) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Create serializer that writes into `output`.
+    pub fn build<'a, W>(self, output: W) -> Serializer<'a, A, W, F, S>
+    where
+        W: io::Write + Sized,
+    {
+        // trailing field separator is optional, the same as the deserializer
+        let mut sep = Vec::new();
+        let sep = match F::field_sep(&mut sep) {
+            Ok(()) => String::from_utf8(sep).unwrap_or_default(),
+            Err(_) => String::new(),
+        };
+
+        let stack = self
+            .prefix
+            .map(|prefix| match prefix.strip_suffix(sep.as_str()) {
+                Some(prefix) if !sep.is_empty() => prefix.to_string(),
+                _ => prefix,
+            })
+            .filter(|prefix| !prefix.is_empty())
+            .map(Cow::Owned)
+            .into_iter()
+            .collect();
+
+        Serializer {
+            output,
+            flag_value: false,
+            stack,
+            case: self.case,
+            sequence: self.sequence,
+            _array: PhantomData,
+            _field: PhantomData,
+            _string: PhantomData,
+        }
+    }
 }
 
 /// Flow that only do 1 field sequential iteration.
 ///
-pub struct SeqFlow<'a, A, W, F, S>
+pub struct SeqFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
     F: FieldFormatter + Sized,
     S: StringFormatter + Sized,
 {
-    ser: &'a mut Serializer<'a, A, W, F, S>,
-    state: State,
+    ser: &'b mut Serializer<'a, A, W, F, S>,
+    // inline elements are kept until the end of the sequence,
+    // since the next element may not be a scalar.
+    mode: Sequence,
+    elements: Vec<Vec<u8>>,
+    index: usize,
 }
 
 /// Flow that supports key & value sequential iteration.
 ///
-pub struct MapFlow<'a, A, W, F, S>
+pub struct MapFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
     F: FieldFormatter + Sized,
     S: StringFormatter + Sized,
 {
-    ser: &'a mut Serializer<'a, A, W, F, S>,
-    key: State,
-    value: State,
+    ser: &'b mut Serializer<'a, A, W, F, S>,
+    // stack depth of the map node, a key is pending when the stack is deeper
+    depth: usize,
 }
 
-impl<'a, A, W, F, S> MapFlow<'a, A, W, F, S>
+impl<'b, 'a, A, W, F, S> MapFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
@@ -213,16 +666,13 @@ where
     S: StringFormatter + Sized,
 {
     #[inline]
-    pub fn initial(ser: &'a mut Serializer<'a, A, W, F, S>) -> Self {
-        Self {
-            ser: ser,
-            key: State::Initial,
-            value: State::Initial,
-        }
+    pub fn initial(ser: &'b mut Serializer<'a, A, W, F, S>) -> Self {
+        let depth = ser.stack.len();
+        Self { ser, depth }
     }
 }
 
-impl<'a, A, W, F, S> SeqFlow<'a, A, W, F, S>
+impl<'b, 'a, A, W, F, S> SeqFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
@@ -230,25 +680,68 @@ where
     S: StringFormatter + Sized,
 {
     #[inline]
-    pub fn initial(ser: &'a mut Serializer<'a, A, W, F, S>) -> Self {
+    pub fn initial(ser: &'b mut Serializer<'a, A, W, F, S>) -> Self {
+        let mode = ser.sequence;
+
         Self {
-            ser: ser,
-            state: State::Initial,
+            ser,
+            mode,
+            elements: Vec::new(),
+            index: 0,
         }
     }
 
-    #[inline]
-    pub fn set_initial(&mut self) {
-        self.state = State::Initial;
+    /// Switch to indexed sequence, by writing kept inline elements under their index.
+    fn indexed(&mut self) -> Result<(), SerializeError> {
+        self.mode = Sequence::Indexed;
+
+        for (index, element) in self.elements.drain(..).enumerate() {
+            self.ser.stack.push(Cow::Owned(index.to_string()));
+            self.ser.render_leaf(|f| f.write_all(&element))?;
+            self.ser.stack.pop();
+        }
+
+        Ok(())
     }
 
-    #[inline]
-    pub fn set_next(&mut self) {
-        self.state = State::Next;
+    /// Close the sequence, inline elements are written in here.
+    ///
+    /// Quotes of elements are only read inside `[..]`, thus array with any quoted
+    /// element that is not bracketed (`1,"a #b"` of [`BareArrayFormatter`]) would be
+    /// read as a quoted value or cut at the comment, it's written as a string instead.
+    ///
+    fn close(&mut self) -> Result<(), SerializeError> {
+        if self.mode == Sequence::Indexed && self.index > 0 {
+            return Ok(());
+        }
+
+        let mut array = Vec::new();
+        let mut quoted = false;
+        A::begin(&mut array)?;
+
+        for (idx, element) in self.elements.drain(..).enumerate() {
+            if idx > 0 {
+                A::separate(&mut array)?;
+            }
+
+            quoted |= matches!(element.first(), Some(b'"') | Some(b'\''));
+            array.extend_from_slice(&element);
+        }
+
+        A::end(&mut array)?;
+
+        if quoted && array.first() != Some(&b'[') {
+            // formatters only write valid utf-8
+            let array =
+                String::from_utf8(array).map_err(|e| SerializeError::CustomError(e.to_string()))?;
+            self.ser.render_leaf(|f| S::format(f, &array))
+        } else {
+            self.ser.render_leaf(|f| f.write_all(&array))
+        }
     }
 }
 
-impl<'a, A, W, F, S> ser::Serializer for &'a mut Serializer<'a, A, W, F, S>
+impl<'b, 'a, A, W, F, S> ser::Serializer for &'b mut Serializer<'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
@@ -259,48 +752,48 @@ where
 
     type Error = SerializeError;
 
-    type SerializeSeq = SeqFlow<'a, A, W, F, S>;
-    type SerializeTuple = SeqFlow<'a, A, W, F, S>;
-    type SerializeTupleStruct = SeqFlow<'a, A, W, F, S>;
-    type SerializeTupleVariant = SeqFlow<'a, A, W, F, S>;
-    type SerializeMap = MapFlow<'a, A, W, F, S>;
-    type SerializeStruct = SeqFlow<'a, A, W, F, S>;
-    type SerializeStructVariant = SeqFlow<'a, A, W, F, S>;
+    type SerializeSeq = SeqFlow<'b, 'a, A, W, F, S>;
+    type SerializeTuple = SeqFlow<'b, 'a, A, W, F, S>;
+    type SerializeTupleStruct = SeqFlow<'b, 'a, A, W, F, S>;
+    type SerializeTupleVariant = SeqFlow<'b, 'a, A, W, F, S>;
+    type SerializeMap = MapFlow<'b, 'a, A, W, F, S>;
+    type SerializeStruct = SeqFlow<'b, 'a, A, W, F, S>;
+    type SerializeStructVariant = SeqFlow<'b, 'a, A, W, F, S>;
 
     #[inline]
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
-        self.render_pair_sep();
-        write!(self.output, "{}", v.to_string()).map_err(Self::Error::from)
+        self.render_leaf(|f| write!(f, "{}", v))
     }
 
     #[inline]
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        self.render_pair_sep();
-        write!(self.output, "{}", v.to_string()).map_err(Self::Error::from)
+        self.render_leaf(|f| write!(f, "{}", v))
     }
 
     #[inline]
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        self.render_pair_sep();
-        write!(self.output, "{}", v.to_string()).map_err(Self::Error::from)
+        self.render_leaf(|f| write!(f, "{}", v))
     }
 
     #[inline]
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        self.render_pair_sep();
-        write!(self.output, "{}", &v.to_string()).map_err(Self::Error::from)
+        self.render_leaf(|f| write!(f, "{}", v))
     }
 
     #[inline]
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        self.render_pair_sep();
-        S::format(&mut self.output, v).map_err(Self::Error::from)
+        self.render_leaf(|f| S::format(f, v))
     }
 
     #[inline]
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        self.render_pair_sep();
-        A::begin(&mut self.output)?;
+        self.ensure_branch()?;
+
+        // top level sequence has no key, even if its elements are indexed
+        if self.stack.is_empty() {
+            return Err(SerializeError::MissingKey);
+        }
+
         Ok(SeqFlow::initial(self))
     }
 
@@ -346,7 +839,6 @@ where
 
     #[inline]
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        self.render_pair_sep();
         use serde::ser::SerializeSeq;
         let mut seq = self.serialize_seq(Some(v.len()))?;
         for byte in v {
@@ -371,13 +863,13 @@ where
 
     #[inline]
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        self.render_pair_sep();
-        write!(&mut self.output, "{}", "null").map_err(Self::Error::from)
+        self.render_leaf(|f| write!(f, "null"))
     }
 
     #[inline]
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_unit()
+        // empty value (`KEY=`), read back as `None` by the deserializer
+        self.render_leaf(|_| Ok(()))
     }
 
     #[inline]
@@ -426,14 +918,12 @@ where
     where
         T: ?Sized + Serialize,
     {
-        // INFO: inside the closure non_value and set_value,
-        //       all calls to serialize_* will not print separator.
-        self.non_value();
-        self.serialize_str(variant)?;
-        self.set_value();
-        // variant.serialize(&mut *self)
-        value.serialize(self)?;
-        F::value_sep(&mut self.output).map_err(Self::Error::from)
+        // externally tagged, variant becomes a field (`CREDENTIAL__PASSWORD=..`)
+        self.ensure_branch()?;
+        self.push_field(variant);
+        value.serialize(&mut *self)?;
+        self.stack.pop();
+        Ok(())
     }
 
     #[inline]
@@ -442,14 +932,16 @@ where
         _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
-        _len: usize,
+        len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        variant.serialize(self)?;
-        Ok(SeqFlow::initial(self))
+        self.ensure_branch()?;
+        self.push_field(variant);
+        self.serialize_seq(Some(len))
     }
 
     #[inline]
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        self.ensure_branch()?;
         Ok(MapFlow::initial(self))
     }
 
@@ -457,12 +949,13 @@ where
     fn serialize_struct(
         self,
         _name: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
         // this only works because of SerializeStruct
         // has the same flow as SerializeSeq since
         // it process both (key, value) tuple at once
-        self.serialize_seq(Some(len))
+        self.ensure_branch()?;
+        Ok(SeqFlow::initial(self))
     }
 
     #[inline]
@@ -473,15 +966,16 @@ where
         variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        variant.serialize(&mut *self)?;
+        self.ensure_branch()?;
+        self.push_field(variant);
         // this only works because of SerializeStruct
         // has the same flow as SerializeSeq since
         // it process both (key, value) tuple at once
-        self.serialize_seq(None)
+        Ok(SeqFlow::initial(self))
     }
 }
 
-impl<'a, A, W, F, S> ser::SerializeSeq for SeqFlow<'a, A, W, F, S>
+impl<'b, 'a, A, W, F, S> ser::SerializeSeq for SeqFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
@@ -495,36 +989,37 @@ where
     where
         T: ?Sized + Serialize,
     {
-
-        match self.state {
-            State::Initial => {
-                // since we only change the state,
-                // it's okay to reorder this like this.
-                self.state = State::Next;
-
-                self.ser.toggle_value();
-                value.serialize(self.ser)
+        let index = self.index;
+        self.index += 1;
+
+        if self.mode == Sequence::Inline {
+            let mut inline = self.ser.inline();
+
+            match value.serialize(&mut inline) {
+                Ok(()) => {
+                    self.elements.push(inline.into_inner());
+                    return Ok(());
+                }
+                // element is a branch, thus every element needs its own key
+                Err(SerializeError::NestedValue) => self.indexed()?,
+                Err(e) => return Err(e),
             }
-            State::Next => {
-                A::separate(&mut self.ser.output)?;
-                value.serialize(self.ser)
-            }
-            _ => Ok(()),
         }
+
+        self.ser.stack.push(Cow::Owned(index.to_string()));
+        value.serialize(&mut *self.ser)?;
+        self.ser.stack.pop();
+        Ok(())
     }
 
     #[inline]
-    fn end(self) -> Result<Self::Ok, Self::Error> {
-        match self.state {
-            // `self.state` shouldn't never get a `State::Initial` in here
-            // since the state always gonna be altered in `Self::serialize_element` function call
-            State::Initial => Err(Self::Error::StateError),
-            _ => A::end(&mut self.ser.output).map_err(Self::Error::from),
-        }
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        // no element in here means empty array (`KEY=[]`)
+        self.close()
     }
 }
 
-impl<'a, A, W, F, S> ser::SerializeTuple for SeqFlow<'a, A, W, F, S>
+impl<'b, 'a, A, W, F, S> ser::SerializeTuple for SeqFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
@@ -548,7 +1043,7 @@ where
     }
 }
 
-impl<'a, A, W, F, S> ser::SerializeTupleStruct for SeqFlow<'a, A, W, F, S>
+impl<'b, 'a, A, W, F, S> ser::SerializeTupleStruct for SeqFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
@@ -572,7 +1067,7 @@ where
     }
 }
 
-impl<'a, A, W, F, S> ser::SerializeTupleVariant for SeqFlow<'a, A, W, F, S>
+impl<'b, 'a, A, W, F, S> ser::SerializeTupleVariant for SeqFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
@@ -589,13 +1084,16 @@ where
     {
         ser::SerializeSeq::serialize_element(self, value)
     }
+
     #[inline]
-    fn end(self) -> Result<Self::Ok, Self::Error> {
-        ser::SerializeSeq::end(self)
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        self.close()?;
+        self.ser.stack.pop();
+        Ok(())
     }
 }
 
-impl<'a, A, W, F, S> ser::SerializeMap for MapFlow<'a, A, W, F, S>
+impl<'b, 'a, A, W, F, S> ser::SerializeMap for MapFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
@@ -609,46 +1107,42 @@ where
     where
         T: ?Sized + Serialize,
     {
-        match self.key {
-            State::Initial => {
-                // this also okay to be reordered since
-                // we only change state.
-                self.key = State::Next;
-                key.serialize(self.ser)
-            }
-            State::Next => {
-                F::field_sep(&mut self.ser.output)?;
-                key.serialize(self.ser)
-            }
+        if self.ser.stack.len() != self.depth {
+            return Err(Self::Error::StateError);
         }
+
+        // map key is kept as is, thus it's read back the same
+        let key = key.serialize(MapKey::<F>::new())?;
+        self.ser.stack.push(Cow::Owned(key));
+        Ok(())
     }
 
     fn serialize_value<T>(&mut self, value: &T) -> Result<Self::Ok, Self::Error>
     where
         T: ?Sized + Serialize,
     {
-        match self.value {
-            State::Initial => {
-                // this also okay to be reordered since
-                // we only change state.
-                self.value = State::Next;
-                value.serialize(self.ser)
-            }
-            State::Next => value.serialize(self.ser),
+        // value without its key
+        if self.ser.stack.len() != self.depth + 1 {
+            return Err(Self::Error::StateError);
         }
+
+        value.serialize(&mut *self.ser)?;
+        self.ser.stack.pop();
+        Ok(())
     }
 
     #[inline]
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        match (self.key, self.value) {
-            (State::Initial, _) => Err(Self::Error::StateError),
-            (_, State::Initial) => Err(Self::Error::StateError),
-            (_, _) => F::value_sep(&mut self.ser.output).map_err(Self::Error::from),
+        // key without its value
+        if self.ser.stack.len() != self.depth {
+            return Err(Self::Error::StateError);
         }
+
+        Ok(())
     }
 }
 
-impl<'a, A, W, F, S> ser::SerializeStruct for SeqFlow<'a, A, W, F, S>
+impl<'b, 'a, A, W, F, S> ser::SerializeStruct for SeqFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
@@ -662,22 +1156,10 @@ where
     where
         T: ?Sized + Serialize,
     {
-
-        match self.state {
-            State::Initial => {
-                // this also okay to be reordered since
-                // we only change state.
-                self.state = State::Next;
-                key.serialize(self.ser)?;
-                value.serialize(self.ser)
-            }
-            State::Next => {
-                F::field_sep(&mut self.ser.output)?;
-                key.serialize(self.ser)?;
-                F::pair_sep(&mut self.ser.output)?;
-                value.serialize(self.ser)
-            }
-        }
+        self.ser.push_field(key);
+        value.serialize(&mut *self.ser)?;
+        self.ser.stack.pop();
+        Ok(())
     }
 
     #[inline]
@@ -686,7 +1168,7 @@ where
     }
 }
 
-impl<'a, A, W, F, S> ser::SerializeStructVariant for SeqFlow<'a, A, W, F, S>
+impl<'b, 'a, A, W, F, S> ser::SerializeStructVariant for SeqFlow<'b, 'a, A, W, F, S>
 where
     W: io::Write + Sized,
     A: ArrayFormatter + Sized,
@@ -705,6 +1187,598 @@ where
 
     #[inline]
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        ser::SerializeStruct::end(self)
+        self.ser.stack.pop();
+        Ok(())
+    }
+}
+
+/// Serialize `value` as env pairs into `writer`.
+///
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<(), SerializeError>
+where
+    W: io::Write,
+    T: ?Sized + Serialize,
+{
+    let mut ser = Serializer::new(writer);
+    value.serialize(&mut ser)
+}
+
+/// Serialize `value` as env pairs into bytes.
+///
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>, SerializeError>
+where
+    T: ?Sized + Serialize,
+{
+    let mut output = Vec::new();
+    to_writer(&mut output, value)?;
+    Ok(output)
+}
+
+/// Serialize `value` as env formatted string.
+///
+pub fn to_string<T>(value: &T) -> Result<String, SerializeError>
+where
+    T: ?Sized + Serialize,
+{
+    // formatters only write valid utf-8
+    String::from_utf8(to_vec(value)?).map_err(|e| SerializeError::CustomError(e.to_string()))
+}
+
+/// Serializer of map key into a field.
+///
+/// Only strings, chars, integers, bools & unit variants are supported,
+/// others are failed with [`SerializeError::InvalidKey`]. The key is
+/// written as is, thus it also fails when it's empty or contains
+/// whitespace, `=`, the field separator of `F` or shell metachar.
+///
+pub(crate) struct MapKey<F> {
+    _field: PhantomData<F>,
+}
+
+impl<F> MapKey<F>
+where
+    F: FieldFormatter + Sized,
+{
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Self {
+            _field: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn invalid<T>(found: &'static str) -> Result<T, SerializeError> {
+        Err(SerializeError::InvalidKey(found.to_string()))
+    }
+
+    /// Field of string key `v`, if it can be read back as is.
+    fn field(v: &str) -> Result<String, SerializeError> {
+        let mut sep = Vec::new();
+        F::field_sep(&mut sep)?;
+        let sep = String::from_utf8_lossy(&sep);
+
+        let invalid = v.is_empty()
+            || (!sep.is_empty() && v.contains(sep.as_ref()))
+            || v.chars().any(|c| {
+                c.is_whitespace() || c.is_control() || "=$`\\\"';&|<>()*?[]{}~!#".contains(c)
+            });
+
+        if invalid {
+            Err(SerializeError::InvalidKey(format!("{:?}", v)))
+        } else {
+            Ok(v.to_string())
+        }
+    }
+}
+
+impl<F> ser::Serializer for MapKey<F>
+where
+    F: FieldFormatter + Sized,
+{
+    type Ok = String;
+
+    type Error = SerializeError;
+
+    type SerializeSeq = ser::Impossible<String, SerializeError>;
+    type SerializeTuple = ser::Impossible<String, SerializeError>;
+    type SerializeTupleStruct = ser::Impossible<String, SerializeError>;
+    type SerializeTupleVariant = ser::Impossible<String, SerializeError>;
+    type SerializeMap = ser::Impossible<String, SerializeError>;
+    type SerializeStruct = ser::Impossible<String, SerializeError>;
+    type SerializeStructVariant = ser::Impossible<String, SerializeError>;
+
+    #[inline]
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    #[inline]
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    #[inline]
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    #[inline]
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    #[inline]
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    #[inline]
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    #[inline]
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    #[inline]
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    #[inline]
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    #[inline]
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Self::invalid("f32")
+    }
+
+    #[inline]
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Self::invalid("f64")
+    }
+
+    #[inline]
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Self::field(v.encode_utf8(&mut [0; 4]))
+    }
+
+    #[inline]
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Self::field(v)
+    }
+
+    #[inline]
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Self::invalid("bytes")
+    }
+
+    #[inline]
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Self::invalid("none")
+    }
+
+    #[inline]
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Self::invalid("unit")
+    }
+
+    #[inline]
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Self::invalid("unit struct")
+    }
+
+    #[inline]
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Self::field(variant)
+    }
+
+    #[inline]
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Self::invalid("newtype variant")
+    }
+
+    #[inline]
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Self::invalid("sequence")
+    }
+
+    #[inline]
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Self::invalid("tuple")
+    }
+
+    #[inline]
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Self::invalid("tuple struct")
+    }
+
+    #[inline]
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Self::invalid("tuple variant")
+    }
+
+    #[inline]
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Self::invalid("map")
+    }
+
+    #[inline]
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Self::invalid("struct")
+    }
+
+    #[inline]
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Self::invalid("struct variant")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Credential {
+        Password { username: String, password: String },
+        Token(String),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Connection {
+        pool: u32,
+        retry: Vec<u32>,
+        timeout: Option<u32>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Database {
+        name: String,
+        connection: Connection,
+        credential: Credential,
+        tenants: BTreeMap<String, u32>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        database: Database,
+    }
+
+    #[test]
+    fn test_to_string_nested_struct() {
+        let config = Config {
+            database: Database {
+                name: "test".to_string(),
+                connection: Connection {
+                    pool: 10,
+                    retry: vec![10, 20, 30],
+                    timeout: None,
+                },
+                credential: Credential::Password {
+                    username: "user".to_string(),
+                    password: "secret".to_string(),
+                },
+                tenants: vec![("acme".to_string(), 5)].into_iter().collect(),
+            },
+        };
+
+        let raw = to_string(&config).unwrap();
+
+        assert_eq!(
+            raw,
+            concat!(
+                "database__name=\"test\"\n",
+                "database__connection__pool=10\n",
+                "database__connection__retry=[10,20,30]\n",
+                "database__connection__timeout=\n",
+                "database__credential__password__username=\"user\"\n",
+                "database__credential__password__password=\"secret\"\n",
+                "database__tenants__acme=5\n",
+            )
+        );
+
+        assert_eq!(crate::from_str::<Config>(&raw).unwrap(), config);
+
+        match to_string(&vec![vec![1]]) {
+            Err(SerializeError::MissingKey) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // top level sequence is rejected, whatever its elements are
+        match to_string(&vec![1]) {
+            Err(SerializeError::MissingKey) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match to_string(&vec![config]) {
+            Err(SerializeError::MissingKey) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match to_string(&1) {
+            Err(SerializeError::MissingKey) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        for key in &["", "a b", "a=b", "a__b", "$(id)", "a;b"] {
+            let mut tenants = BTreeMap::new();
+            tenants.insert(key.to_string(), 1);
+
+            match to_string(&tenants) {
+                Err(SerializeError::InvalidKey(found)) => assert_eq!(found, format!("{:?}", key)),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        let mut tenants = BTreeMap::new();
+        tenants.insert("acme-1.eu", 1);
+        assert_eq!(to_string(&tenants).unwrap(), "acme-1.eu=1\n");
+    }
+
+    #[test]
+    fn test_serializer_builder() {
+        let mut tenants = BTreeMap::new();
+        tenants.insert("acme", "gold");
+
+        let mut ser = Builder::new()
+            .string_formatter::<RawStringFormatter>()
+            .build(Vec::new());
+
+        tenants.serialize(&mut ser).unwrap();
+        assert_eq!(ser.into_inner(), b"acme=gold\n");
+
+        let mut output = Vec::new();
+        to_writer(&mut output, &tenants).unwrap();
+        assert_eq!(output, to_vec(&tenants).unwrap());
+        assert_eq!(to_string(&tenants).unwrap(), "acme=\"gold\"\n");
+    }
+
+    #[test]
+    fn test_serializer_prefix_case() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Application {
+            env: String,
+            logger: BTreeMap<String, String>,
+        }
+
+        let mut logger = BTreeMap::new();
+        logger.insert("Level".to_string(), "info".to_string());
+
+        let application = Application {
+            env: "development".to_string(),
+            logger,
+        };
+
+        let mut ser = Builder::new()
+            .prefix("CONFIG__")
+            .key_case(KeyCase::Upper)
+            .build(Vec::new());
+
+        application.serialize(&mut ser).unwrap();
+
+        let raw = String::from_utf8(ser.into_inner()).unwrap();
+
+        assert_eq!(
+            raw,
+            "CONFIG__ENV=\"development\"\nCONFIG__LOGGER__Level=\"info\"\n"
+        );
+
+        let envs = crate::Envs::from_str(&raw, crate::de::Options::default().prefix("CONFIG"));
+
+        assert_eq!(
+            envs.unwrap().deserialize::<Application>().unwrap(),
+            application
+        );
+    }
+
+    #[test]
+    fn test_serializer_sequence() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Server {
+            host: String,
+            port: u16,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Upstream {
+            retry: Vec<u32>,
+            servers: Vec<Server>,
+            empty: Vec<u32>,
+            hosts: Vec<String>,
+        }
+
+        let upstream = Upstream {
+            retry: vec![10, 20],
+            servers: vec![
+                Server {
+                    host: "a".to_string(),
+                    port: 80,
+                },
+                Server {
+                    host: "b".to_string(),
+                    port: 81,
+                },
+            ],
+            empty: Vec::new(),
+            hosts: vec!["a \"b\"".to_string(), "c,d".to_string()],
+        };
+
+        // non scalar elements are always indexed
+        let mut ser = Builder::new()
+            .array_formatter::<BareArrayFormatter>()
+            .build(Vec::new());
+
+        upstream.serialize(&mut ser).unwrap();
+
+        let raw = String::from_utf8(ser.into_inner()).unwrap();
+
+        assert_eq!(
+            raw,
+            concat!(
+                "retry=10,20\n",
+                "servers__0__host=\"a\"\n",
+                "servers__0__port=80\n",
+                "servers__1__host=\"b\"\n",
+                "servers__1__port=81\n",
+                "empty=\n",
+                // quoted elements are written as a string, thus escaped twice
+                r#"hosts="\"a \\\"b\\\"\",\"c,d\"""#,
+                "\n",
+            )
+        );
+
+        assert_eq!(crate::from_str::<Upstream>(&raw).unwrap(), upstream);
+
+        let mut ser = Builder::new().sequence(Sequence::Indexed).build(Vec::new());
+
+        upstream.serialize(&mut ser).unwrap();
+
+        let raw = String::from_utf8(ser.into_inner()).unwrap();
+
+        assert!(raw.starts_with("retry__0=10\nretry__1=20\nservers__0__host="));
+        assert!(raw.ends_with(concat!(
+            "empty=[]\n",
+            r#"hosts__0="a \"b\"""#,
+            "\n",
+            r#"hosts__1="c,d""#,
+            "\n",
+        )));
+        assert_eq!(crate::from_str::<Upstream>(&raw).unwrap(), upstream);
+
+        // quoted element after the first one keeps its comment char
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Labels {
+            t: Vec<Option<String>>,
+            u: (u32, String),
+        }
+
+        let labels = Labels {
+            t: vec![None, Some("x #y".to_string())],
+            u: (1, "a #b".to_string()),
+        };
+
+        let mut ser = Builder::new()
+            .array_formatter::<BareArrayFormatter>()
+            .build(Vec::new());
+
+        labels.serialize(&mut ser).unwrap();
+
+        let raw = String::from_utf8(ser.into_inner()).unwrap();
+
+        assert_eq!(
+            raw,
+            concat!(r#"t=",\"x #y\"""#, "\n", r#"u="1,\"a #b\"""#, "\n")
+        );
+        assert_eq!(crate::from_str::<Labels>(&raw).unwrap(), labels);
+    }
+
+    fn format<S>(v: &str) -> io::Result<String>
+    where
+        S: StringFormatter,
+    {
+        let mut output = Vec::new();
+        S::format(&mut output, v)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_string_formatters() {
+        let v = "it's \"$HOME\" `id` \\\n\tend";
+
+        assert_eq!(
+            format::<DefaultStringFormatter>(v).unwrap(),
+            "\"it's \\\"$HOME\\\" `id` \\\\\\n\\tend\""
+        );
+        assert_eq!(
+            format::<PosixStringFormatter>(v).unwrap(),
+            "'it'\\''s \"$HOME\" `id` \\\n\tend'"
+        );
+        assert_eq!(
+            format::<BashStringFormatter>("it's \\\n\x01").unwrap(),
+            "$'it\\'s \\\\\\n\\x01'"
+        );
+        assert_eq!(
+            format::<FishStringFormatter>("it's \\").unwrap(),
+            "'it\\'s \\\\'"
+        );
+        assert_eq!(
+            format::<RawStringFormatter>("mysql://host:3306/db").unwrap(),
+            "mysql://host:3306/db"
+        );
+
+        assert!(format::<RawStringFormatter>("a b").is_err());
+        assert!(format::<RawStringFormatter>("a,b").is_err());
+        assert!(format::<PosixStringFormatter>("a\0b").is_err());
+
+        // default formatter round-trips through the deserializer
+        let mut map = BTreeMap::new();
+        map.insert("key".to_string(), v.to_string());
+
+        let raw = to_string(&map).unwrap();
+        assert_eq!(
+            crate::from_str::<BTreeMap<String, String>>(&raw).unwrap(),
+            map
+        );
     }
 }
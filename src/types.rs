@@ -1,7 +1,7 @@
-///!
-///! Most abstract trait for serialization & deserialization purposes.
-///!
-///!
+//!
+//! Most abstract trait for serialization & deserialization purposes.
+//!
+//!
 use std::io;
 
 /// Trait that give a way to format a quoted string.
@@ -69,5 +69,8 @@ pub trait FieldFormatter {
         W: io::Write + ?Sized;
 }
 
-
-pub trait Writer<W, A, F, S> where W: io::Write {}
+pub trait Writer<W, A, F, S>
+where
+    W: io::Write,
+{
+}